@@ -1,25 +1,398 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::{
+    config::Defaults,
+    filter::{Filter, SortBy, YearRange},
+    pipeline::{PageFormat, Pipeline, ReadingOrder, ResizeFilter, Spreads},
+    profile::Profile,
+    progress::ProgressFormat,
+    range::ChapterSelection,
+    strip::LongStrip,
+    types::{DownloadType, SaveType, Thread},
+};
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Encoding {
     Unicode,
     Ascii,
 }
 
-// Get the encoding from the command line arguments
-pub fn get_encoding(args: &Vec<String>) -> Encoding {
-    if args.iter().any(|arg| arg == "--ascii") {
-        Encoding::Ascii
-    } else {
-        Encoding::Unicode
+#[derive(Parser, Debug)]
+#[command(
+    name = "mangadl-rs",
+    version,
+    about = "Simple manga downloader, from mangasee123.com"
+)]
+#[command(after_help = "Run without a subcommand to start the interactive wizard.")]
+pub struct Cli {
+    /// Use ascii characters instead of unicode
+    #[arg(long, global = true)]
+    pub ascii: bool,
+
+    /// How download progress is shown
+    #[arg(long, global = true, value_enum, default_value_t = ProgressFormat::Bars)]
+    pub progress: ProgressFormat,
+
+    /// How many times a failed request is tried before giving up [default: 5]
+    #[arg(long, global = true)]
+    pub retries: Option<u32>,
+
+    /// Requests sent to one host at once, whatever the number of threads [default: 4]
+    #[arg(long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    pub connections_per_host: Option<u16>,
+
+    /// Directory the downloads are saved to [default: the current directory]
+    #[arg(short, long, global = true)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Download the catalogue again, even when the cached one is recent
+    #[arg(long, global = true, conflicts_with = "offline")]
+    pub refresh: bool,
+
+    /// Browse the cached catalogue without going online, e.g. when the site is down
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Config file to use instead of the default one
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    // Get the encoding from the command line arguments
+    pub fn encoding(&self) -> Encoding {
+        if self.ascii {
+            Encoding::Ascii
+        } else {
+            Encoding::Unicode
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Search the catalogue and print the ids of matching manga
+    Search(SearchArgs),
+    /// Show the details of a manga
+    Info {
+        /// Manga id, as printed by `search`
+        id: String,
+    },
+    /// List the chapters of a manga
+    Chapters {
+        /// Manga id, as printed by `search`
+        id: String,
+    },
+    /// Download chapters of a manga
+    Download(DownloadArgs),
+    /// Download the new chapters of every manga in the library
+    Update(UpdateArgs),
+    /// Keep running, downloading new chapters of the library as they come out
+    Watch(WatchArgs),
+    /// Show and edit the library of downloaded manga
+    Library {
+        #[command(subcommand)]
+        command: LibraryCommand,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    /// Text to look for in the titles or authors, best matches are printed first
+    pub query: Option<String>,
+
+    /// Only show manga having this genre, can be repeated
+    #[arg(short, long)]
+    pub genre: Vec<String>,
+
+    /// Only show manga having at least one of these genres, can be repeated
+    #[arg(long)]
+    pub any_genre: Vec<String>,
+
+    /// Hide manga having this genre, can be repeated
+    #[arg(short = 'x', long)]
+    pub exclude_genre: Vec<String>,
+
+    /// Scanlation status, e.g. ongoing or complete
+    #[arg(long)]
+    pub scan_status: Option<String>,
+
+    /// Publication status, e.g. ongoing, complete or hiatus
+    #[arg(long)]
+    pub publish_status: Option<String>,
+
+    /// Type, e.g. manga, manhwa, manhua or one-shot
+    #[arg(long = "type", value_name = "TYPE")]
+    pub kind: Option<String>,
+
+    /// Years of release, e.g. 2010-2015, 2010-, -2015 or 2012
+    #[arg(short, long, value_parser = parse_years)]
+    pub year: Option<YearRange>,
+
+    /// Only show manga marked as hot
+    #[arg(long)]
+    pub hot: bool,
+
+    /// Order of the results, instead of the best matches first
+    #[arg(short, long, value_enum)]
+    pub sort: Option<SortBy>,
+}
+
+impl SearchArgs {
+    // Filter built from the options.
+    pub fn filter(&self) -> Filter {
+        let mut filter = Filter::new()
+            .with_genres(self.genre.clone())
+            .with_any_genre(self.any_genre.clone())
+            .without_genres(self.exclude_genre.clone());
+        if let Some(status) = &self.scan_status {
+            filter = filter.with_scan_status(status);
+        }
+        if let Some(status) = &self.publish_status {
+            filter = filter.with_publish_status(status);
+        }
+        if let Some(kind) = &self.kind {
+            filter = filter.with_type(kind);
+        }
+        if let Some(years) = self.year {
+            filter = filter.with_years(years);
+        }
+        if self.hot {
+            filter = filter.hot_only();
+        }
+        if let Some(sort) = self.sort {
+            filter = filter.sorted_by(sort);
+        }
+        filter
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct UpdateArgs {
+    /// Only update these manga, every manga in the library when omitted
+    pub ids: Vec<String>,
+
+    /// How the images are downloaded [default: single]
+    #[arg(short, long, value_enum)]
+    pub download: Option<DownloadType>,
+
+    /// Number of threads to use [default: 1]
+    #[arg(short, long, value_parser = parse_thread)]
+    pub threads: Option<Thread>,
+
+    #[command(flatten)]
+    pub images: ImageArgs,
+}
+
+impl UpdateArgs {
+    // Options given on the command line, the config filling in the others.
+    pub fn defaults(&self) -> Defaults {
+        Defaults {
+            output_dir: None,
+            save: None,
+            download: self.download,
+            threads: self.threads,
+            images: self.images.pipeline(),
+            profile: self.images.profile,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// Minutes between two checks of the catalogue
+    #[arg(short, long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+
+    #[command(flatten)]
+    pub update: UpdateArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LibraryCommand {
+    /// List the manga in the library, or the chapters of one
+    List {
+        /// Manga id, lists every manga when omitted
+        id: Option<String>,
+    },
+    /// Mark chapters as read
+    Read {
+        /// Manga id, as printed by `library list`
+        id: String,
+        /// Chapters to mark, e.g. `1-50,72,100.5-,latest:5` or `all`
+        #[arg(default_value = "all", value_parser = parse_chapters)]
+        chapters: ChapterSelection,
+    },
+    /// Mark chapters as unread
+    Unread {
+        /// Manga id, as printed by `library list`
+        id: String,
+        /// Chapters to mark, e.g. `1-50,72,100.5-,latest:5` or `all`
+        #[arg(default_value = "all", value_parser = parse_chapters)]
+        chapters: ChapterSelection,
+    },
+    /// Remove a manga, or some of its chapters, from the library. Downloaded files are kept
+    Remove {
+        /// Manga id, as printed by `library list`
+        id: String,
+        /// Chapters to remove, the whole manga when omitted
+        #[arg(value_parser = parse_chapters)]
+        chapters: Option<ChapterSelection>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct DownloadArgs {
+    /// Manga id, as printed by `search`
+    pub id: String,
+
+    /// Chapters to download, e.g. `1-50,72,100.5-,latest:5` or `all`
+    #[arg(short, long, default_value = "all", value_parser = parse_chapters)]
+    pub chapters: ChapterSelection,
+
+    /// How the chapters are saved [default: pdf-split]
+    #[arg(short, long, value_enum)]
+    pub save: Option<SaveType>,
+
+    /// How the images are downloaded [default: single]
+    #[arg(short, long, value_enum)]
+    pub download: Option<DownloadType>,
+
+    /// Number of threads to use [default: 1]
+    #[arg(short, long, value_parser = parse_thread)]
+    pub threads: Option<Thread>,
+
+    #[command(flatten)]
+    pub images: ImageArgs,
+}
+
+impl DownloadArgs {
+    // Options given on the command line, the config filling in the others.
+    pub fn defaults(&self) -> Defaults {
+        Defaults {
+            output_dir: None,
+            save: self.save.clone(),
+            download: self.download,
+            threads: self.threads,
+            images: self.images.pipeline(),
+            profile: self.images.profile,
+        }
     }
 }
 
-pub fn display_help(args: &Vec<String>) -> bool {
-    if args.iter().any(|args| args == "--help") {
-        println!("Usage: mangadl-rs [OPTIONS]");
-        println!("\nOptions:");
-        println!("  --ascii\t\tUse ascii characters instead of unicode");
-        println!("  --help\t\tDisplay this help message");
-        return true;
+// Changes made to every page before it is saved, on top of the configured ones.
+#[derive(Args, Debug, Clone, Default)]
+#[command(next_help_heading = "Image processing")]
+pub struct ImageArgs {
+    /// Device the pages are made for, choosing the size, colors and save type to suit it
+    #[arg(long, value_enum)]
+    pub profile: Option<Profile>,
+
+    /// Shrink pages wider than this many pixels
+    #[arg(long)]
+    pub max_width: Option<u32>,
+
+    /// Shrink pages higher than this many pixels
+    #[arg(long)]
+    pub max_height: Option<u32>,
+
+    /// Filter used to shrink pages [default: lanczos3]
+    #[arg(long, value_enum)]
+    pub resize_filter: Option<ResizeFilter>,
+
+    /// Convert pages to grayscale
+    #[arg(long)]
+    pub grayscale: bool,
+
+    /// Cut away the uniform white or black borders around the art
+    #[arg(long)]
+    pub crop_margins: bool,
+
+    /// How far from the border color a pixel of the border may stray, out of 255 [default: 16]
+    #[arg(long)]
+    pub crop_tolerance: Option<u8>,
+
+    /// Percent of the width or height cropped at most [default: 15]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub crop_limit: Option<u8>,
+
+    /// What is done with double-page spreads, pages wider than high [default: keep]
+    #[arg(long, value_enum)]
+    pub spreads: Option<Spreads>,
+
+    /// Order the pages of a spread are read in [default: right-to-left]
+    #[arg(long, value_enum)]
+    pub reading_order: Option<ReadingOrder>,
+
+    /// Also save spreads as they were, before the pages made of them
+    #[arg(long)]
+    pub keep_spreads: bool,
+
+    /// Stitch the pages of manhwa and manhua into a strip per chapter, and slice it again [default: off]
+    #[arg(long, value_enum)]
+    pub long_strip: Option<LongStrip>,
+
+    /// Target height of the slices of a long strip [default: the max height, or 1.5 times the width]
+    #[arg(long)]
+    pub strip_height: Option<u32>,
+
+    /// Change the contrast by this many percent, e.g. 20 or -10
+    #[arg(long, allow_negative_numbers = true)]
+    pub contrast: Option<f32>,
+
+    /// Gamma correction, above 1 lightens and below 1 darkens
+    #[arg(long)]
+    pub gamma: Option<f32>,
+
+    /// Bits per color channel, e.g. 4 for the 16 grays of e-ink [default: 8]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub bits: Option<u8>,
+
+    /// Format pages are saved in [default: as served]
+    #[arg(long, value_enum)]
+    pub format: Option<PageFormat>,
+
+    /// Quality of jpeg and avif pages, from 1 to 100 [default: 85]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: Option<u8>,
+}
+
+impl ImageArgs {
+    pub fn pipeline(&self) -> Pipeline {
+        Pipeline {
+            max_width: self.max_width,
+            max_height: self.max_height,
+            resize_filter: self.resize_filter,
+            grayscale: self.grayscale.then_some(true),
+            crop_margins: self.crop_margins.then_some(true),
+            crop_tolerance: self.crop_tolerance,
+            crop_limit: self.crop_limit,
+            spreads: self.spreads,
+            reading_order: self.reading_order,
+            keep_spreads: self.keep_spreads.then_some(true),
+            long_strip: self.long_strip,
+            strip_height: self.strip_height,
+            contrast: self.contrast,
+            gamma: self.gamma,
+            bits: self.bits,
+            format: self.format,
+            quality: self.quality,
+        }
     }
-    false
+}
+
+fn parse_chapters(s: &str) -> Result<ChapterSelection, String> {
+    s.parse::<ChapterSelection>().map_err(|e| e.to_string())
+}
+
+fn parse_years(s: &str) -> Result<YearRange, String> {
+    s.parse::<YearRange>().map_err(|e| e.to_string())
+}
+
+fn parse_thread(s: &str) -> Result<Thread, String> {
+    s.parse::<Thread>().map_err(|e| e.to_string())
 }
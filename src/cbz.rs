@@ -0,0 +1,156 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    chapter::ChapterInfo, error::Result, manga::Manga, profile::PageSize, progress::chapter_name,
+    range::ChapterNumber,
+};
+
+// Metadata read by comic servers and readers (Komga, Kavita, Tachiyomi, ...).
+// See https://anansi-project.github.io/docs/comicinfo/documentation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComicInfo {
+    pub title: String,
+    pub series: String,
+    pub number: Option<String>,
+    pub year: Option<String>,
+    pub month: Option<String>,
+    pub day: Option<String>,
+    pub writer: Vec<String>,
+    pub genre: Vec<String>,
+    pub notes: Vec<String>,
+    pub page_count: usize,
+    pub right_to_left: bool,
+}
+
+impl ComicInfo {
+    // Metadata of an archive holding the whole series.
+    pub fn series(manga: &Manga, page_count: usize) -> Self {
+        let mut notes = Vec::new();
+        if !manga.al.is_empty() {
+            notes.push(format!("Also known as: {}", manga.al.join(", ")));
+        }
+        notes.push(format!("Scan status: {}", manga.ss));
+        notes.push(format!("Publish status: {}", manga.ps));
+
+        Self {
+            title: manga.s.clone(),
+            series: manga.s.clone(),
+            number: None,
+            year: Some(manga.y.clone()).filter(|year| !year.is_empty()),
+            month: None,
+            day: None,
+            writer: manga.a.clone(),
+            genre: manga.g.clone(),
+            notes,
+            page_count,
+            right_to_left: manga.is_right_to_left(),
+        }
+    }
+
+    // Metadata of an archive holding a single chapter, dated with its release when it is known.
+    pub fn chapter(
+        manga: &Manga,
+        chapter: &str,
+        info: Option<&ChapterInfo>,
+        page_count: usize,
+    ) -> Self {
+        let mut comic_info = Self::series(manga, page_count);
+        // Readers show these as they are, so "0010.5" is written as "10.5".
+        comic_info.title = format!("{} - {}", manga.s, chapter_name(chapter));
+        comic_info.number = Some(ChapterNumber::display_id(chapter));
+
+        // Mangasee dates look like "2023-03-05 12:34:56".
+        if let Some(date) = info.and_then(|info| info.Date.as_str()) {
+            let mut parts = date
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|part| !part.is_empty());
+            comic_info.year = parts.next().map(str::to_string).or(comic_info.year);
+            comic_info.month = parts.next().map(str::to_string);
+            comic_info.day = parts.next().map(str::to_string);
+        }
+        comic_info
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+        );
+        // Elements follow the order of the schema, which strict readers validate.
+        let mut push = |tag: &str, value: &str| {
+            if !value.is_empty() {
+                xml.push_str(&format!("  <{tag}>{}</{tag}>\n", escape(value)));
+            }
+        };
+        push("Title", &self.title);
+        push("Series", &self.series);
+        push("Number", self.number.as_deref().unwrap_or_default());
+        push("Notes", &self.notes.join("\n"));
+        push("Year", self.year.as_deref().unwrap_or_default());
+        push("Month", self.month.as_deref().unwrap_or_default());
+        push("Day", self.day.as_deref().unwrap_or_default());
+        push("Writer", &self.writer.join(", "));
+        push("Genre", &self.genre.join(", "));
+        push("PageCount", &self.page_count.to_string());
+        push(
+            "Manga",
+            if self.right_to_left {
+                "YesAndRightToLeft"
+            } else {
+                "Yes"
+            },
+        );
+        xml.push_str("</ComicInfo>\n");
+        xml
+    }
+}
+
+// Write a cbz archive holding `comic_info` and the `images`, in the given order.
+// With a page size the images are fitted to it, otherwise they are stored as they are.
+pub fn write_cbz(
+    path: &Path,
+    comic_info: &ComicInfo,
+    images: &[PathBuf],
+    page_size: Option<PageSize>,
+) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    // Images are already compressed, deflating them again only costs time.
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file("ComicInfo.xml", options)?;
+    zip.write_all(comic_info.to_xml().as_bytes())?;
+
+    for image in images {
+        let name = image
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut bytes = fs::read(image)?;
+        if let Some(page_size) = page_size {
+            bytes = page_size.fit_bytes(bytes)?;
+        }
+        zip.start_file(name, options)?;
+        zip.write_all(&bytes)?;
+    }
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
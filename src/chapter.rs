@@ -1,15 +1,13 @@
 #![allow(non_snake_case)]
 
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{
-    fmt::{Display, Formatter},
-    time::Duration,
-};
-use tokio::time::sleep;
+use std::fmt::{Display, Formatter};
 
-const URL: &str = "https://mangasee123.com/";
+use crate::{
+    error::{Error, Result},
+    range::ChapterNumber,
+};
 
 // Names taken directly from mangasee123, so they are not snake case. Rename was deemed unnecessary.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -20,30 +18,8 @@ pub struct ChapterInfo {
 }
 
 impl ChapterInfo {
-    pub async fn list(manga_id: &str) -> Result<Vec<ChapterInfo>, Box<dyn std::error::Error>> {
-        let page = reqwest::get(format!("{URL}manga/{manga_id}"))
-            .await?
-            .text()
-            .await?;
-        Ok(serde_json::from_str(
-            Regex::new(r#"vm\.Chapters = (.*);"#)
-                .expect("Failed to create regex")
-                .captures(&page)
-                .expect("Failed to find chapter list")
-                .get(1)
-                .expect("Failed to get chapter list")
-                .as_str(),
-        )?)
-    }
-
     pub fn to_url_id(&self) -> String {
-        let chapter = self.Chapter[1..self.Chapter.len() - 1].to_string();
-        let odd = self.Chapter[self.Chapter.len() - 1..].to_string();
-        if odd == "0" {
-            chapter
-        } else {
-            format!("{}.{}", chapter, odd)
-        }
+        Chapter::decode_id(&self.Chapter).unwrap_or_else(|_| self.Chapter.clone())
     }
 }
 
@@ -55,82 +31,42 @@ pub struct Chapter {
 }
 
 impl Chapter {
-    pub async fn list(
-        manga_id: &str,
-        valid_chapter: &str,
-    ) -> Result<Vec<Chapter>, Box<dyn std::error::Error>> {
-        let chapter = valid_chapter[1..valid_chapter.len() - 1].to_string();
-        let odd = valid_chapter[valid_chapter.len() - 1..].to_string();
+    // Sources only hand out chapters whose encoding decodes, the raw encoding is kept otherwise.
+    #[must_use]
+    pub fn to_url_id(&self) -> String {
+        Chapter::decode_id(&self.Chapter).unwrap_or_else(|_| self.Chapter.clone())
+    }
 
-        let valid_chapter = if odd == "0" {
-            chapter
+    // Decode mangasee's chapter encoding, "102345" being chapter 234.5 of the first index.
+    pub fn decode_id(encoded: &str) -> Result<String> {
+        // An index digit, the chapter and its tenths.
+        if encoded.len() < 3 || !encoded.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::Layout("a valid chapter id"));
+        }
+        let (chapter, odd) = encoded[1..].split_at(encoded.len() - 2);
+        if odd == "0" {
+            Ok(chapter.to_string())
         } else {
-            format!("{}.{}", chapter, odd)
-        };
-
-        let page = reqwest::get(format!(
-            "{URL}read-online/{manga_id}-chapter-{valid_chapter}.html"
-        ))
-        .await?
-        .text()
-        .await?;
-        Ok(serde_json::from_str(
-            Regex::new(r#"vm\.CHAPTERS = (.*);"#)
-                .expect("Failed to create regex")
-                .captures(&page)
-                .expect("Failed to find chapter list")
-                .get(1)
-                .expect("Failed to get chapter list")
-                .as_str(),
-        )?)
+            Ok(format!("{}.{}", chapter, odd))
+        }
     }
 
-    pub async fn cur_path_name(&self, manga: &str) -> String {
-        let chapter = self.clone();
-        let page = loop {
-            match loop {
-                match reqwest::get(format!("{URL}read-online/{manga}-chapter-{chapter}.html")).await
-                {
-                    Ok(data) => break data,
-                    Err(e) => {
-                        eprintln!("Error: {e}, Retrying!");
-                        sleep(Duration::from_millis(50)).await;
-                        continue;
-                    }
-                }
-            }
-            .text()
-            .await
-            {
-                Ok(data) => break data,
-                Err(e) => {
-                    eprintln!("Error: {e}, Retrying!");
-                    sleep(Duration::from_millis(50)).await;
-                    continue;
-                }
-            }
-        };
-
-        Regex::new(r#"vm\.CurPathName = (.*);"#)
-            .expect("Failed to create regex")
-            .captures(&page)
-            .expect("Failed to find chapter list")
-            .get(1)
-            .expect("Failed to get chapter list")
-            .as_str()
-            .trim()
-            .replace('\"', "")
+    // Number of the chapter, for comparing and selecting chapters in ranges.
+    #[must_use]
+    pub fn number(&self) -> ChapterNumber {
+        // Everything but the leading index digit already is the chapter in tenths.
+        ChapterNumber(
+            self.Chapter
+                .get(1..)
+                .and_then(|tenths| tenths.parse().ok())
+                .unwrap_or_default(),
+        )
     }
 
+    // Index digit of mangasee's chapter encoding, "1" unless a series restarts its numbering.
     #[must_use]
-    pub fn to_url_id(&self) -> String {
-        let chapter = self.Chapter[1..self.Chapter.len() - 1].to_string();
-        let odd = self.Chapter[self.Chapter.len() - 1..].to_string();
-        if odd == "0" {
-            chapter
-        } else {
-            format!("{}.{}", chapter, odd)
-        }
+    pub fn volume(&self) -> String {
+        self.Chapter.get(..1).unwrap_or_default().to_string()
     }
 
     #[must_use]
@@ -148,3 +84,35 @@ impl Display for Chapter {
         write!(f, "{}", self.to_url_id())
     }
 }
+
+// A single page of a chapter, and where to download it from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub chapter: String,
+    pub volume: String,
+    pub number: usize,
+    pub url: String,
+}
+
+impl Page {
+    // Sortable name of the page, without extension. E.g. "0012.5-003".
+    #[must_use]
+    pub fn stem(&self) -> String {
+        format!("{:0>4}-{:0>3}", self.chapter, self.number)
+    }
+
+    // Extension taken from the url, "png" when there is none.
+    #[must_use]
+    pub fn extension(&self) -> &str {
+        self.url
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.rsplit_once('.'))
+            .map_or("png", |(_, ext)| ext)
+    }
+
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.stem(), self.extension())
+    }
+}
@@ -0,0 +1,230 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    error::Result,
+    fetch::DownloadOptions,
+    output::{Output, Templates},
+    pipeline::Pipeline,
+    profile::Profile,
+    progress::ProgressSink,
+    retry::RetryPolicy,
+    source::DEFAULT_TTL,
+    store::read_if_exists,
+    types::{DownloadType, SaveType, Thread},
+};
+
+const FILE_NAME: &str = "config.toml";
+
+// How a download is saved, when not given on the command line.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Defaults {
+    pub output_dir: Option<PathBuf>,
+    pub save: Option<SaveType>,
+    pub download: Option<DownloadType>,
+    pub threads: Option<Thread>,
+    pub images: Pipeline,
+    // Device the pages are made for, filling in the save type and image settings not set.
+    pub profile: Option<Profile>,
+}
+
+impl Defaults {
+    // These defaults, falling back to `other` for the ones not set.
+    #[must_use]
+    pub fn or(&self, other: &Defaults) -> Defaults {
+        Defaults {
+            output_dir: self.output_dir.clone().or_else(|| other.output_dir.clone()),
+            save: self.save.clone().or_else(|| other.save.clone()),
+            download: self.download.or(other.download),
+            threads: self.threads.or(other.threads),
+            images: self.images.or(&other.images),
+            profile: self.profile.or(other.profile),
+        }
+    }
+
+    // These defaults, the profile filling in the save type and image settings not set.
+    #[must_use]
+    pub fn apply_profile(&self) -> Defaults {
+        let mut defaults = self.clone();
+        if let Some(profile) = self.profile {
+            defaults.save = defaults.save.or_else(|| Some(profile.save_type()));
+            defaults.images = defaults.images.or(&profile.pipeline());
+        }
+        defaults
+    }
+
+    // Options of a download, using the built in defaults for the ones not set.
+    #[must_use]
+    pub fn into_options(
+        self,
+        templates: &Templates,
+        progress: Arc<dyn ProgressSink>,
+    ) -> DownloadOptions {
+        let defaults = self.apply_profile();
+        DownloadOptions {
+            save_type: defaults.save.unwrap_or(SaveType::PdfSplit),
+            download_type: defaults.download.unwrap_or(DownloadType::Single),
+            threads: defaults.threads.unwrap_or_default(),
+            output: defaults
+                .output_dir
+                .map_or_else(Output::default, Output::new)
+                .with_templates(templates.clone()),
+            progress,
+            pipeline: defaults.images,
+            page_size: defaults.profile.map(|profile| profile.page_size()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RetryConfig {
+    pub attempts: Option<u32>,
+    pub base_delay_ms: Option<u64>,
+    pub max_delay_ms: Option<u64>,
+    // Longest wait a server may ask for before a retry.
+    pub max_retry_after_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CatalogueConfig {
+    // Minutes the cached catalogue is used before downloading it again.
+    pub ttl_minutes: Option<u64>,
+}
+
+// Settings read from "config.toml", e.g.
+//
+// output-dir = "~/Manga"
+// save = "cbz-split"
+// profile = "kobo-clara"
+// threads = 4
+// user-agent = "mangadl-rs"
+// connections-per-host = 4
+//
+// [retry]
+// attempts = 8
+//
+// [catalogue]
+// ttl-minutes = 60
+//
+// [images]
+// grayscale = true
+//
+// [templates]
+// chapter-file = "{series}/{series} - Chapter {chapter}.{ext}"
+//
+// [series.One-Piece]
+// save = "epub"
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    #[serde(flatten)]
+    pub defaults: Defaults,
+    pub retry: RetryConfig,
+    pub catalogue: CatalogueConfig,
+    pub user_agent: Option<String>,
+    // Requests sent to one host at once, whatever the number of threads.
+    pub connections_per_host: Option<usize>,
+    pub templates: Templates,
+    // Whether the wizard skips the questions answered by the config, instead of only preselecting them.
+    pub skip_prompts: bool,
+    // Overrides of the defaults for single manga, keyed by id.
+    pub series: HashMap<String, Defaults>,
+}
+
+impl Config {
+    // Where the config is kept, e.g. "~/.config/mangadl-rs/config.toml" on linux.
+    #[must_use]
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_default()
+            .join("mangadl-rs")
+            .join(FILE_NAME)
+    }
+
+    // Load the config from `file`, or from the default path when there is none.
+    // Only the default config may be missing, a file asked for has to exist.
+    pub fn load(file: Option<&Path>) -> Result<Self> {
+        let text = match file {
+            Some(file) => fs::read_to_string(file)?,
+            None => match read_if_exists(&Self::default_path(), fs::read_to_string)? {
+                Some(text) => text,
+                None => return Ok(Self::default()),
+            },
+        };
+        let mut config: Config = toml::from_str(&text)?;
+        config.templates.check()?;
+        config.defaults.images.check()?;
+        for defaults in config.series.values() {
+            defaults.images.check()?;
+        }
+        config.defaults.output_dir = config.defaults.output_dir.map(expand_home);
+        for defaults in config.series.values_mut() {
+            defaults.output_dir = defaults.output_dir.take().map(expand_home);
+        }
+        Ok(config)
+    }
+
+    // Write every manga to `dir`, whatever the config says.
+    pub fn set_output_dir(&mut self, dir: PathBuf) {
+        self.defaults.output_dir = Some(dir);
+        for defaults in self.series.values_mut() {
+            defaults.output_dir = None;
+        }
+    }
+
+    // Defaults of the manga `id`, its overrides taking precedence.
+    #[must_use]
+    pub fn for_series(&self, id: &str) -> Defaults {
+        match self.series.get(id) {
+            Some(overrides) => overrides.or(&self.defaults),
+            None => self.defaults.clone(),
+        }
+    }
+
+    // How long the cached catalogue is used.
+    #[must_use]
+    pub fn catalogue_ttl(&self) -> Duration {
+        self.catalogue
+            .ttl_minutes
+            .map_or(DEFAULT_TTL, |minutes| Duration::from_secs(minutes * 60))
+    }
+
+    // Retry policy, `attempts` from the command line taking precedence.
+    #[must_use]
+    pub fn retry_policy(&self, attempts: Option<u32>) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy::new(
+            attempts
+                .or(self.retry.attempts)
+                .unwrap_or(default.max_attempts),
+            self.retry
+                .base_delay_ms
+                .map_or(default.base_delay, Duration::from_millis),
+            self.retry
+                .max_delay_ms
+                .map_or(default.max_delay, Duration::from_millis),
+        )
+        .with_max_retry_after(
+            self.retry
+                .max_retry_after_secs
+                .map_or(default.max_retry_after, Duration::from_secs),
+        )
+    }
+}
+
+// "~/Manga" is not expanded by the shell when written in the config.
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path,
+    }
+}
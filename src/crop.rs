@@ -0,0 +1,144 @@
+use image::DynamicImage;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+// How far from the background color a margin pixel may stray, out of 255, when not configured.
+pub const DEFAULT_TOLERANCE: u8 = 16;
+
+// Most of the width or of the height cut away, in percent, when not configured.
+pub const DEFAULT_LIMIT: u8 = 15;
+
+// Share of the pixels of a margin line that may stray further, for dust and scan noise.
+const NOISE: f32 = 0.005;
+
+// Trims the uniform white or black borders scans come with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub tolerance: u8,
+    // Percent of the width or height that is cut away at most, however wide the borders look.
+    pub limit: u8,
+}
+
+// Share of every side of a page that is margin, from 0 to 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Margins {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+impl Default for Crop {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOLERANCE, DEFAULT_LIMIT)
+    }
+}
+
+impl Crop {
+    #[must_use]
+    pub fn new(tolerance: u8, limit: u8) -> Self {
+        Self {
+            tolerance,
+            limit: limit.min(100),
+        }
+    }
+
+    // Margins of `image` in the color of its corners, `None` for a blank page.
+    #[must_use]
+    pub fn margins(&self, image: &DynamicImage) -> Option<Margins> {
+        let gray = image.to_luma8();
+        let (width, height) = gray.dimensions();
+        // The middle two of the four corners, so a speck of dust in one does not throw it off.
+        let mut corners = [
+            (0, 0),
+            (width - 1, 0),
+            (0, height - 1),
+            (width - 1, height - 1),
+        ]
+        .map(|(x, y)| gray.get_pixel(x, y).0[0]);
+        corners.sort_unstable();
+        let background = ((u16::from(corners[1]) + u16::from(corners[2])) / 2) as u8;
+        let strays =
+            |x: u32, y: u32| gray.get_pixel(x, y).0[0].abs_diff(background) > self.tolerance;
+        let row = |y: u32| !is_blank((0..width).filter(|&x| strays(x, y)).count(), width);
+
+        let top = (0..height).find(|&y| row(y))?;
+        let bottom = (top..height).rev().find(|&y| row(y)).unwrap_or(top);
+        let lines = bottom - top + 1;
+        let column = |x: u32| !is_blank((top..=bottom).filter(|&y| strays(x, y)).count(), lines);
+        let left = (0..width).find(|&x| column(x)).unwrap_or(0);
+        let right = (left..width).rev().find(|&x| column(x)).unwrap_or(left);
+
+        let margins = Margins {
+            top: top as f32 / height as f32,
+            bottom: (height - 1 - bottom) as f32 / height as f32,
+            left: left as f32 / width as f32,
+            right: (width - 1 - right) as f32 / width as f32,
+        };
+        Some(margins.limited(self.limit))
+    }
+
+    // `image` without its margins.
+    #[must_use]
+    pub fn crop(&self, image: DynamicImage) -> DynamicImage {
+        match self.margins(&image) {
+            Some(margins) => margins.apply(image),
+            None => image,
+        }
+    }
+
+    // The margins every one of `images` has, so the pages of a chapter are cut alike and keep the same size.
+    #[must_use]
+    pub fn common_margins(&self, images: &[DynamicImage]) -> Margins {
+        images
+            .par_iter()
+            .filter_map(|image| self.margins(image))
+            .reduce_with(Margins::min)
+            .unwrap_or_default()
+    }
+}
+
+// Whether a line with `strays` pixels off the background out of `len` is still margin.
+fn is_blank(strays: usize, len: u32) -> bool {
+    strays as f32 <= len as f32 * NOISE
+}
+
+impl Margins {
+    // The smaller margin of every side.
+    #[must_use]
+    pub fn min(self, other: Margins) -> Margins {
+        Margins {
+            top: self.top.min(other.top),
+            bottom: self.bottom.min(other.bottom),
+            left: self.left.min(other.left),
+            right: self.right.min(other.right),
+        }
+    }
+
+    // Shrunk so that no more than `limit` percent of the width or of the height is cut away.
+    fn limited(self, limit: u8) -> Margins {
+        let limit = f32::from(limit) / 100.0;
+        let scale = |a: f32, b: f32| if a + b > limit { limit / (a + b) } else { 1.0 };
+        let vertical = scale(self.top, self.bottom);
+        let horizontal = scale(self.left, self.right);
+        Margins {
+            top: self.top * vertical,
+            bottom: self.bottom * vertical,
+            left: self.left * horizontal,
+            right: self.right * horizontal,
+        }
+    }
+
+    // `image` with the margins cut away.
+    #[must_use]
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let left = (self.left * width).round() as u32;
+        let top = (self.top * height).round() as u32;
+        let kept_width = ((1.0 - self.left - self.right) * width).round().max(1.0) as u32;
+        let kept_height = ((1.0 - self.top - self.bottom) * height).round().max(1.0) as u32;
+        if (kept_width, kept_height) == (image.width(), image.height()) {
+            return image;
+        }
+        image.crop_imm(left, top, kept_width, kept_height)
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{cbz::escape, error::Result, manga::Manga, profile::PageSize, time::utc_now};
+
+// A chapter of the book and the images of its pages, in reading order.
+pub struct EpubChapter {
+    pub title: String,
+    pub images: Vec<PathBuf>,
+}
+
+struct EpubPage {
+    id: String,
+    image_name: String,
+    media_type: &'static str,
+    width: u32,
+    height: u32,
+}
+
+// Write a fixed-layout EPUB 3, one xhtml page per image and a navigation entry per chapter.
+// Pages are as large as their image, or all of `page_size` with the image centered on them.
+pub fn write_epub(
+    path: &Path,
+    manga: &Manga,
+    chapters: &[EpubChapter],
+    page_size: Option<PageSize>,
+) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype has to come first and uncompressed, readers sniff it.
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER.as_bytes())?;
+
+    let mut toc = Vec::new();
+    let mut pages = Vec::new();
+    for chapter in chapters {
+        for (i, image) in chapter.images.iter().enumerate() {
+            let ext = image
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let id = format!("p{:05}", pages.len() + 1);
+            let (width, height) = match page_size {
+                Some(page_size) => (page_size.width, page_size.height),
+                None => image::image_dimensions(image)?,
+            };
+            let page = EpubPage {
+                image_name: format!("{id}.{ext}"),
+                media_type: media_type(&ext),
+                id,
+                width,
+                height,
+            };
+
+            zip.start_file(format!("OEBPS/images/{}", page.image_name), stored)?;
+            zip.write_all(&fs::read(image)?)?;
+            zip.start_file(format!("OEBPS/{}.xhtml", page.id), deflated)?;
+            zip.write_all(page_xhtml(&page).as_bytes())?;
+
+            if i == 0 {
+                toc.push((chapter.title.as_str(), page.id.clone()));
+            }
+            pages.push(page);
+        }
+    }
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(nav_xhtml(manga, &toc).as_bytes())?;
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(content_opf(manga, &pages, page_size).as_bytes())?;
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn page_xhtml(page: &EpubPage) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{id}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: 100%; height: 100%; object-fit: contain; }}</style>
+</head>
+<body>
+  <img src="images/{image}" alt=""/>
+</body>
+</html>
+"#,
+        id = page.id,
+        width = page.width,
+        height = page.height,
+        image = page.image_name,
+    )
+}
+
+fn nav_xhtml(manga: &Manga, toc: &[(&str, String)]) -> String {
+    let entries = toc
+        .iter()
+        .map(|(title, id)| {
+            format!(
+                "      <li><a href=\"{id}.xhtml\">{}</a></li>\n",
+                escape(title)
+            )
+        })
+        .collect::<String>();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>{title}</h1>
+    <ol>
+{entries}    </ol>
+  </nav>
+</body>
+</html>
+"#,
+        title = escape(&manga.s),
+    )
+}
+
+fn content_opf(manga: &Manga, pages: &[EpubPage], page_size: Option<PageSize>) -> String {
+    let mut metadata = format!(
+        "    <dc:identifier id=\"book-id\">urn:mangadl-rs:{}</dc:identifier>\n    \
+         <dc:title>{}</dc:title>\n    <dc:language>en</dc:language>\n",
+        escape(&manga.i),
+        escape(&manga.s)
+    );
+    for author in &manga.a {
+        metadata.push_str(&format!(
+            "    <dc:creator>{}</dc:creator>\n",
+            escape(author)
+        ));
+    }
+    for genre in &manga.g {
+        metadata.push_str(&format!("    <dc:subject>{}</dc:subject>\n", escape(genre)));
+    }
+    if !manga.y.is_empty() {
+        metadata.push_str(&format!("    <dc:date>{}</dc:date>\n", escape(&manga.y)));
+    }
+    metadata.push_str(&format!(
+        "    <meta property=\"dcterms:modified\">{}</meta>\n    \
+         <meta property=\"rendition:layout\">pre-paginated</meta>\n    \
+         <meta property=\"rendition:orientation\">portrait</meta>\n    \
+         <meta property=\"rendition:spread\">none</meta>\n",
+        utc_now()
+    ));
+    // Kindle and Kobo lay the book out for this screen size.
+    if let Some(page_size) = page_size {
+        metadata.push_str(&format!(
+            "    <meta name=\"original-resolution\" content=\"{}x{}\"/>\n",
+            page_size.width, page_size.height
+        ));
+    }
+
+    let mut manifest =
+        String::from("    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n");
+    let mut spine = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        let cover = if i == 0 {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        };
+        manifest.push_str(&format!(
+            "    <item id=\"{id}-img\" href=\"images/{image}\" media-type=\"{media_type}\"{cover}/>\n    \
+             <item id=\"{id}\" href=\"{id}.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            id = page.id,
+            image = page.image_name,
+            media_type = page.media_type,
+        ));
+        spine.push_str(&format!("    <itemref idref=\"{}\"/>\n", page.id));
+    }
+
+    // Manga is read right to left, manhwa and manhua left to right.
+    let direction = if manga.is_right_to_left() {
+        "rtl"
+    } else {
+        "ltr"
+    };
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine page-progression-direction="{direction}">
+{spine}  </spine>
+</package>
+"#
+    )
+}
+
+fn media_type(ext: &str) -> &'static str {
+    match ext {
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        _ => "image/png",
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+
+    #[error("{url} responded with {status}")]
+    Status {
+        url: String,
+        status: reqwest::StatusCode,
+        retry_after: Option<Duration>,
+    },
+
+    #[error(
+        "{url} asked to wait {}s before retrying, longer than the {}s allowed",
+        retry_after.as_secs(),
+        max.as_secs()
+    )]
+    RetryAfter {
+        url: String,
+        retry_after: Duration,
+        max: Duration,
+    },
+
+    // The site changed its page layout, so the scraped variable could not be found.
+    #[error("Page layout changed, failed to find {0}")]
+    Layout(&'static str),
+
+    #[error("Invalid chapter range \"{0}\", expected e.g. 1-50,72,100.5-,latest:5 or all")]
+    Range(String),
+
+    #[error("Invalid year range \"{0}\", expected e.g. 2010-2015, 2010-, -2015 or 2012")]
+    Years(String),
+
+    #[error("Invalid config: {0}")]
+    Config(#[from] toml::de::Error),
+
+    #[error("Unknown placeholder {{{0}}} in output template")]
+    Template(String),
+
+    #[error("Output template \"{0}\" leaves the output directory")]
+    TemplatePath(String),
+
+    #[error("No cached catalogue to browse offline, run once while online")]
+    NoCatalogue,
+
+    #[error("Failed to decode json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Failed to decode image: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("Image processing: {0}")]
+    Processing(String),
+
+    #[error("Failed to create pdf: {0}")]
+    Pdf(String),
+
+    #[error("Failed to write archive: {0}")]
+    Archive(#[from] zip::result::ZipError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -1,357 +1,1064 @@
-use std::{collections::BTreeMap, fs::File, io::BufWriter, path::Path};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use image_to_pdf::ImageToPdf;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use manic::Client;
 use printpdf::image_crate::DynamicImage;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::{
+    prelude::{
+        IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+    },
+    slice::ParallelSliceMut,
+};
 use std::io::Write;
-use tokio::fs;
+use tokio::{
+    fs,
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+};
 
 use crate::{
-    chapter::Chapter,
+    cbz::{write_cbz, ComicInfo},
+    chapter::{Chapter, ChapterInfo, Page},
+    crop::Crop,
+    epub::{write_epub, EpubChapter},
+    error::{Error, Result},
     manga::Manga,
+    manifest::{Manifest, PageEntry},
+    output::Output,
+    pipeline::{encode, PageFormat, Pipeline, DEFAULT_QUALITY},
+    profile::PageSize,
+    progress::{chapter_name, ChapterTracker, Event, Phase, ProgressSink, Silent},
+    scheduler::Scheduler,
+    source::Source,
+    strip::{stitch, Strip},
     types::{DownloadType, SaveType, Thread},
 };
 
-pub async fn get_img(client: Client, url: &str) -> Result<DynamicImage, reqwest::Error> {
-    Ok(
-        image::load_from_memory(&client.get(url).send().await?.bytes().await?)
-            .expect("Failed to load image"),
-    )
+// A page that could not be downloaded, even after retrying.
+#[derive(Debug)]
+pub struct FailedPage {
+    pub page: Page,
+    pub error: Error,
+}
+
+// Extension of the image format, sniffed from the magic bytes at the start of the file.
+// The extension in the url is not to be trusted, some sites serve jpegs as ".png".
+#[must_use]
+pub fn sniff_extension(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("jpg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f', ..] => Some("avif"),
+        _ => None,
+    }
+}
+
+// Download a page and run it through `pipeline`, returning the raw bytes of the pages saved for it.
+async fn fetch_page(source: &dyn Source, pipeline: &Pipeline, url: &str) -> Result<Vec<Vec<u8>>> {
+    let bytes = source.fetch_image(url).await?;
+    if pipeline.is_identity() {
+        return Ok(vec![bytes]);
+    }
+    // Decoding and encoding take long enough to stall the other downloads.
+    let pipeline = pipeline.clone();
+    tokio::task::spawn_blocking(move || pipeline.process(bytes))
+        .await
+        .map_err(|e| Error::Processing(e.to_string()))?
+}
+
+// Write the parts of a page to `path_for` the extension of their format, and record them in the manifest.
+// A page only has several parts when a spread was split, "3.jpg" being followed by "3a.jpg" and "3b.jpg".
+async fn write_page(
+    manifest: &Arc<Mutex<Manifest>>,
+    page: &Page,
+    path_for: impl Fn(&str) -> PathBuf,
+    parts: Vec<Vec<u8>>,
+) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for (i, bytes) in parts.into_iter().enumerate() {
+        let path = path_for(sniff_extension(&bytes).unwrap_or_else(|| page.extension()));
+        let path = part_path(&path, i);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&path, &bytes).await?;
+        files.push((path, bytes));
+    }
+    let paths = files.iter().map(|(path, _)| path.clone()).collect();
+
+    // Hashed and written off the async workers, the manifest only being locked to add the page.
+    let manifest = manifest.clone();
+    let url = page.url.clone();
+    tokio::task::spawn_blocking(move || {
+        let Some(entry) = PageEntry::new(&files) else {
+            return Ok(());
+        };
+        let snapshot = manifest.lock().unwrap().record(&url, entry)?;
+        snapshot.map_or(Ok(()), |snapshot| snapshot.write())
+    })
+    .await
+    .expect("Manifest task panicked")?;
+    Ok(paths)
+}
+
+// Write the manifest from a blocking task, the lock only being held to take a snapshot of it.
+async fn save_manifest(manifest: &Mutex<Manifest>) -> Result<()> {
+    let snapshot = manifest.lock().unwrap().snapshot()?;
+    tokio::task::spawn_blocking(move || snapshot.write())
+        .await
+        .expect("Manifest task panicked")
+}
+
+// `path` with the letter of the part `i` added to its name, the first part keeping it as is.
+fn part_path(path: &Path, i: usize) -> PathBuf {
+    let Some(letter) = i.checked_sub(1).and_then(|i| (b'a'..=b'z').nth(i)) else {
+        return path.to_path_buf();
+    };
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(char::from(letter).to_string());
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+// Size of the parts of a page.
+fn parts_size(parts: &[Vec<u8>]) -> u64 {
+    parts.iter().map(|part| part.len() as u64).sum()
+}
+
+// Where a previous run saved the parts of the page, and their size, if they are at `path_for` and still intact.
+// Only the entry of the page is taken under the lock, its files are read and hashed in a blocking task.
+async fn resumed(
+    manifest: &Mutex<Manifest>,
+    page: &Page,
+    path_for: impl Fn(&str) -> PathBuf,
+) -> Option<(Vec<PathBuf>, u64)> {
+    let entry = manifest.lock().unwrap().pages.get(&page.url)?.clone();
+    if !entry.is_at(path_for) {
+        return None;
+    }
+    tokio::task::spawn_blocking(move || Some((entry.intact_paths()?, entry.total_size())))
+        .await
+        .expect("Manifest task panicked")
+}
+
+// Lay `image` out on the page, when there is a page size.
+fn fit_page(image: DynamicImage, page_size: Option<PageSize>) -> DynamicImage {
+    match page_size {
+        Some(page_size) => page_size.fit(image),
+        None => image,
+    }
+}
+
+// Create the file at `path`, and the folders leading to it that templates may have added.
+fn create_file(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(File::create(path)?)
+}
+
+// How and where a download is saved.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub save_type: SaveType,
+    pub download_type: DownloadType,
+    pub threads: Thread,
+    pub output: Output,
+    pub progress: Arc<dyn ProgressSink>,
+    // Applied to every page before it is saved, the url list aside.
+    pub pipeline: Pipeline,
+    // Size every page of a pdf, epub or cbz is laid out on, the size of the image when not set.
+    pub page_size: Option<PageSize>,
+}
+
+// What is done to the decoded pages of a chapter as a whole, rather than page by page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChapterLayout {
+    // The margins all the pages have are cut, so they keep the same size.
+    pub crop: Option<Crop>,
+    // The pages are stitched into a long strip and cut again.
+    pub strip: Option<Strip>,
+}
+
+impl ChapterLayout {
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        self.crop.is_none() && self.strip.is_none()
+    }
+
+    // The pages of a chapter laid out, not always as many as there were.
+    #[must_use]
+    pub fn apply(&self, images: Vec<DynamicImage>) -> Vec<DynamicImage> {
+        match (self.strip, self.crop) {
+            // Cropped once stitched, so the gaps between the panels at the ends of the cuts are kept.
+            (Some(strip), Some(crop)) => match stitch(images) {
+                Some(stitched) => strip.cut(crop.crop(stitched)),
+                None => Vec::new(),
+            },
+            (Some(strip), None) => strip.restrip(images),
+            (None, Some(crop)) => {
+                let margins = crop.common_margins(&images);
+                images
+                    .into_par_iter()
+                    .map(|image| margins.apply(image))
+                    .collect()
+            }
+            (None, None) => images,
+        }
+    }
 }
 
 pub async fn download_manga(
+    source: Arc<dyn Source>,
     manga: Manga,
     chapters: Vec<Chapter>,
-    save_type: SaveType,
-    download_type: DownloadType,
-    threads: Thread,
-    unicode: bool,
-) {
-    println!("Fetching urls...");
-    let urls = manga.chapters_urls(threads, unicode, chapters).await;
-    match (save_type, download_type) {
-        (SaveType::Urls, _) => urls_download(urls, &manga),
-        (SaveType::Images, DownloadType::Single) => {
-            images_download(false, unicode, urls, &manga, 1).await;
+    options: &DownloadOptions,
+) -> Result<Vec<FailedPage>> {
+    // Pdf pages are cropped a chapter at a time once decoded, so the pages of a chapter keep the same size.
+    // The cuts of a long strip are cropped and sliced once stitched, whatever the save type.
+    let pdf = matches!(options.save_type, SaveType::PdfSingle | SaveType::PdfSplit);
+    let strip = options
+        .pipeline
+        .strip()
+        .filter(|_| manga.is_long_strip() && options.save_type != SaveType::Urls);
+    let layout = ChapterLayout {
+        crop: options.pipeline.crop().filter(|_| pdf || strip.is_some()),
+        strip,
+    };
+    let per_page;
+    let options = if layout.is_none() {
+        options
+    } else {
+        per_page = DownloadOptions {
+            pipeline: match strip {
+                Some(_) => options.pipeline.for_strips(),
+                None => options.pipeline.without_crop(),
+            },
+            // A chapter in a single image is scrolled through, not laid out on pages.
+            page_size: options.page_size.filter(|_| strip != Some(Strip::Whole)),
+            ..options.clone()
+        };
+        &per_page
+    };
+    let DownloadOptions {
+        save_type,
+        download_type,
+        threads,
+        output,
+        progress,
+        pipeline,
+        page_size,
+    } = options;
+    let page_size = *page_size;
+
+    // Avif pages can be written, but not decoded again to lay them out, or to measure them for an epub.
+    pipeline.check()?;
+    let decoded = match save_type {
+        SaveType::PdfSingle | SaveType::PdfSplit | SaveType::Epub => true,
+        SaveType::CbzSingle | SaveType::CbzSplit => page_size.is_some(),
+        _ => false,
+    } || !layout.is_none();
+    if pipeline.format == Some(PageFormat::Avif) && decoded {
+        return Err(Error::Processing(format!(
+            "avif pages can not be saved as {save_type:?}{}",
+            if page_size.is_some() || !layout.is_none() {
+                " when they are laid out"
+            } else {
+                ""
+            }
+        )));
+    }
+    if *save_type != SaveType::Urls {
+        // Pages a previous run processed differently are downloaded again.
+        let mut manifest = Manifest::load(&output.manga_dir(&manga))?;
+        if manifest.set_processing(&pipeline.fingerprint()) {
+            save_manifest(&Mutex::new(manifest)).await?;
         }
-        (SaveType::Images, DownloadType::Multi) => {
-            images_download(false, unicode, urls, &manga, threads.get() as usize).await;
+    }
+
+    // A single download fetches one thing at a time, except for the url list which is nothing but fetching urls.
+    let workers = match (download_type, save_type) {
+        (DownloadType::Multi, _) | (_, SaveType::Urls) => threads.get() as usize,
+        (DownloadType::Single, _) => 1,
+    };
+    let scheduler = Scheduler::new(workers);
+
+    let pages = manga
+        .chapters_pages(source.clone(), &scheduler, progress, chapters)
+        .await?;
+    let failed = match save_type {
+        SaveType::Urls => {
+            urls_download(pages, &manga, output, progress.as_ref()).map(|_| Vec::new())
         }
-        (SaveType::PdfSingle, DownloadType::Single) => {
-            save_to_pdf(download_to_ram(unicode, urls, 1).await, &manga, unicode);
+        SaveType::Images | SaveType::ImagesChapter if layout.strip.is_some() => {
+            let folder = *save_type == SaveType::ImagesChapter;
+            let (cached, mut failed) =
+                download_to_cache(source, pages, &manga, options, &scheduler).await?;
+            let (cached, unreadable) = lay_out_cache(cached, &manga, options, layout)?;
+            failed.extend(unreadable);
+            save_images(&cached, &manga, output, folder, progress.as_ref())?;
+            Ok(failed)
         }
-        (SaveType::PdfSingle, DownloadType::Multi) => {
-            save_to_pdf(
-                download_to_ram(unicode, urls, threads.get() as usize).await,
-                &manga,
-                unicode,
-            );
+        SaveType::Images => {
+            images_download(source, false, pages, &manga, options, &scheduler).await
         }
-        (SaveType::PdfSplit, DownloadType::Single) => {
-            save_to_pdf_split_chapters(download_to_ram(unicode, urls, 1).await, &manga, unicode);
+        SaveType::ImagesChapter => {
+            images_download(source, true, pages, &manga, options, &scheduler).await
         }
-        (SaveType::PdfSplit, DownloadType::Multi) => {
-            save_to_pdf_split_chapters(
-                download_to_ram(unicode, urls, threads.get() as usize).await,
-                &manga,
-                unicode,
-            );
+        SaveType::PdfSingle => {
+            let (images, failed) =
+                download_to_ram(source, pages, &manga, options, layout, &scheduler).await?;
+            save_to_pdf(images, &manga, output, page_size, progress.as_ref())?;
+            Ok(failed)
         }
-        (SaveType::ImagesChapter, DownloadType::Single) => {
-            images_download(true, unicode, urls, &manga, 1).await;
+        SaveType::PdfSplit => {
+            download_to_pdf_split_chapters(source, pages, &manga, options, layout, &scheduler).await
         }
-        (SaveType::ImagesChapter, DownloadType::Multi) => {
-            images_download(true, unicode, urls, &manga, threads.get() as usize).await;
+        SaveType::CbzSingle => {
+            let (cached, mut failed) =
+                download_to_cache(source, pages, &manga, options, &scheduler).await?;
+            let (cached, unreadable) = lay_out_cache(cached, &manga, options, layout)?;
+            failed.extend(unreadable);
+            save_to_cbz(&cached, &manga, output, page_size, progress.as_ref())?;
+            Ok(failed)
         }
-    }
+        SaveType::CbzSplit => {
+            let infos = source.chapter_infos(&manga).await.unwrap_or_else(|e| {
+                progress.event(Event::Warning {
+                    message: format!("{e}, Chapters will not be dated"),
+                });
+                Vec::new()
+            });
+            let (cached, mut failed) =
+                download_to_cache(source, pages, &manga, options, &scheduler).await?;
+            let (cached, unreadable) = lay_out_cache(cached, &manga, options, layout)?;
+            failed.extend(unreadable);
+            save_to_cbz_split_chapters(
+                &cached,
+                &manga,
+                output,
+                &infos,
+                page_size,
+                progress.as_ref(),
+            )?;
+            Ok(failed)
+        }
+        SaveType::Epub => {
+            let (cached, mut failed) =
+                download_to_cache(source, pages, &manga, options, &scheduler).await?;
+            let (cached, unreadable) = lay_out_cache(cached, &manga, options, layout)?;
+            failed.extend(unreadable);
+            save_to_epub(&cached, &manga, output, page_size, progress.as_ref())?;
+            Ok(failed)
+        }
+    }?;
+    progress.event(Event::Finished {
+        failed: failed.len(),
+    });
+    Ok(failed)
 }
 
 // Download urls seperated by a a line into a 1 text file named the manga.
-pub fn urls_download(urls: Vec<String>, manga: &Manga) {
-    let mut file = File::create(format!("{}.txt", manga.i)).expect("Failed to create file");
-    for url in urls {
-        file.write_all(format!("{}\n", url).as_bytes())
-            .expect("Failed to write to file");
+pub fn urls_download(
+    pages: Vec<Page>,
+    manga: &Manga,
+    output: &Output,
+    progress: &dyn ProgressSink,
+) -> Result<()> {
+    progress.event(Event::Phase {
+        phase: Phase::Write,
+        total: 1,
+    });
+    let path = output.series_path(manga, "txt");
+    let mut file = create_file(&path)?;
+    for page in pages {
+        file.write_all(format!("{}\n", page.url).as_bytes())?;
     }
+    progress.event(Event::FileWritten { path });
+    Ok(())
 }
 
 pub async fn images_download(
+    source: Arc<dyn Source>,
     folder: bool,
-    unicode: bool,
-    urls: Vec<String>,
+    pages: Vec<Page>,
     manga: &Manga,
-    threads: usize,
-) {
-    println!("Downlading images...");
-    // Set progress bar
-    let m = MultiProgress::new();
-
-    let sty = if unicode {
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .unwrap()
-    } else {
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .expect("Failed to create progress style")
-        .progress_chars("#>-")
-    };
+    options: &DownloadOptions,
+    scheduler: &Scheduler,
+) -> Result<Vec<FailedPage>> {
+    let DownloadOptions {
+        output,
+        progress,
+        pipeline,
+        ..
+    } = options;
+    progress.event(Event::Phase {
+        phase: Phase::Download,
+        total: pages.len(),
+    });
+    let tracker = Arc::new(ChapterTracker::new(progress.clone(), &pages));
+    let manifest = Arc::new(Mutex::new(Manifest::load(&output.manga_dir(manga))?));
 
-    // Split urls into <threads> parts.
-    let mut urls_split = Vec::new();
-    for _ in 0..threads {
-        urls_split.push(Vec::new());
-    }
-    for (i, url) in urls.iter().enumerate() {
-        urls_split[i % threads].push(url.clone());
-    }
-
-    let mut urls_for_progress = urls_split.clone();
-
-    let mut progress_bars = Vec::new();
-    progress_bars.push(
-        m.add(ProgressBar::new(
-            urls_for_progress
-                .pop()
-                .expect("failed to get previous url")
-                .len() as u64,
-        )),
-    );
-    for _ in 0..threads - 1 {
-        progress_bars.push(
-            m.insert_after(
-                &progress_bars
-                    .clone()
-                    .pop()
-                    .expect("failed to get previous bar to insert after"),
-                ProgressBar::new(
-                    urls_for_progress
-                        .pop()
-                        .expect("failed to get url len for bar")
-                        .len() as u64,
-                ),
-            ),
-        );
-    }
-    for (i, bar) in progress_bars.iter().enumerate() {
-        bar.set_style(sty.clone());
-        bar.set_message(format!("Part {}", i + 1));
-    }
-
-    // Spawn a threads for each part.
-    let mut handles = Vec::new();
-    for (urls, bar) in urls_split.into_iter().zip(progress_bars) {
-        let manga = manga.clone();
-        let handle = tokio::spawn(async move {
-            let client = Client::new();
-            for url in urls {
-                bar.inc(1);
-                let img = loop {
-                    match get_img(client.clone(), url.as_str()).await {
-                        Ok(img) => break img,
-                        Err(e) => {
-                            eprintln!("{e}\nFailed to download image, Retrying...");
-                            std::thread::sleep(std::time::Duration::from_millis(100));
-                            continue;
-                        }
-                    }
-                };
+    let results = scheduler
+        .run(pages, |page| {
+            let manga = manga.clone();
+            let output = output.clone();
+            let source = source.clone();
+            let pipeline = pipeline.clone();
+            let manifest = manifest.clone();
+            let tracker = tracker.clone();
+            async move {
+                tracker.started(&page);
+                let path_for = |ext: &str| output.page_path(&manga, &page, folder, ext);
 
-                let path = if folder {
-                    format!(
-                        "{name}/{chapter}/{page}",
-                        name = &manga.i,
-                        chapter = &url.split('/').last().unwrap().split('-').next().unwrap(),
-                        page = &url.split('/').last().unwrap().split('-').last().unwrap(),
-                    )
-                } else {
-                    format!(
-                        "{name}/{page}",
-                        name = &manga.i,
-                        page = &url.split('/').last().unwrap(),
-                    )
-                };
+                // Already saved by a previous run.
+                if let Some((_, size)) = resumed(&manifest, &page, path_for).await {
+                    tracker.done(&page, size, true);
+                    return Ok(None);
+                }
 
-                let file_path = Path::new(&path);
-                fs::create_dir_all(file_path.parent().unwrap())
-                    .await
-                    .unwrap();
-                img.save(file_path).expect("Failed to save image");
+                // Saved as served, images are only decoded when they have to be processed.
+                match fetch_page(source.as_ref(), &pipeline, &page.url).await {
+                    Ok(parts) => {
+                        let size = parts_size(&parts);
+                        write_page(&manifest, &page, path_for, parts).await?;
+                        tracker.done(&page, size, false);
+                        Ok(None)
+                    }
+                    Err(error) => {
+                        tracker.failed(&page, &error);
+                        Ok::<_, Error>(Some(FailedPage { page, error }))
+                    }
+                }
             }
-        });
-        handles.push(handle);
-    }
-    // Wait for all threads to finish.
-    for handle in handles {
-        handle.await.unwrap();
+        })
+        .await;
+
+    let mut failed = Vec::new();
+    for result in results {
+        failed.extend(result?);
     }
+    save_manifest(&manifest).await?;
+    Ok(failed)
 }
 
+// Download pages and decode them into memory, laying them out a chapter at a time.
+// Keyed by chapter and position in it.
 pub async fn download_to_ram(
-    unicode: bool,
-    urls: Vec<String>,
-    threads: usize,
-) -> BTreeMap<String, DynamicImage> {
-    println!("Downloading images to ram...");
-    // Set progress bar
-    let m = MultiProgress::new();
-
-    let sty = if unicode {
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .unwrap()
-    } else {
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .expect("Failed to create progress style")
-        .progress_chars("#>-")
-    };
+    source: Arc<dyn Source>,
+    pages: Vec<Page>,
+    manga: &Manga,
+    options: &DownloadOptions,
+    layout: ChapterLayout,
+    scheduler: &Scheduler,
+) -> Result<(BTreeMap<String, DynamicImage>, Vec<FailedPage>)> {
+    let progress = options.progress.as_ref();
+    let (cached, mut failed) = download_to_cache(source, pages, manga, options, scheduler).await?;
 
-    // Split urls into <threads> parts.
-    let mut urls_split = Vec::new();
-    for _ in 0..threads {
-        urls_split.push(Vec::new());
-    }
-    for (i, url) in urls.iter().enumerate() {
-        urls_split[i % threads].push(url.clone());
-    }
-
-    let mut urls_for_progress = urls_split.clone();
-
-    let mut progress_bars = Vec::new();
-    progress_bars.push(
-        m.add(ProgressBar::new(
-            urls_for_progress
-                .pop()
-                .expect("failed to get previous url")
-                .len() as u64,
-        )),
-    );
-    for _ in 0..threads - 1 {
-        progress_bars.push(
-            m.insert_after(
-                &progress_bars
-                    .clone()
-                    .pop()
-                    .expect("failed to get previous bar to insert after"),
-                ProgressBar::new(
-                    urls_for_progress
-                        .pop()
-                        .expect("failed to get url len for bar")
-                        .len() as u64,
-                ),
-            ),
-        );
-    }
-    for (i, bar) in progress_bars.iter().enumerate() {
-        bar.set_style(sty.clone());
-        bar.set_message(format!("Part {}", i + 1));
-    }
-
-    let mut handles = Vec::new();
-    for (urls, bar) in urls_split.into_iter().zip(progress_bars) {
-        let handle = tokio::spawn(async move {
-            let client = Client::new();
-            let mut images = BTreeMap::new();
-            for url in urls {
-                bar.inc(1);
-                let img = loop {
-                    match get_img(client.clone(), url.as_str()).await {
-                        Ok(img) => break img,
-                        Err(e) => {
-                            eprintln!("{e}\nFailed to download image, Retrying...");
-                            std::thread::sleep(std::time::Duration::from_millis(100));
-                            continue;
-                        }
+    progress.event(Event::Phase {
+        phase: Phase::Decode,
+        total: cached.len(),
+    });
+    let mut images = BTreeMap::new();
+    for (chapter, cached) in by_chapter(cached) {
+        let (decoded, unreadable) = decode_pages(cached, progress);
+        failed.extend(unreadable);
+        for (i, image) in layout.apply(decoded).into_iter().enumerate() {
+            images.insert(format!("{chapter:0>4}-{i:04}"), image);
+        }
+    }
+    Ok((images, failed))
+}
+
+// Cached pages grouped by chapter, keeping their order.
+fn by_chapter(cached: Cached) -> BTreeMap<String, Cached> {
+    let mut chapters = BTreeMap::<String, Cached>::new();
+    for (page, path) in cached {
+        chapters
+            .entry(page.chapter.clone())
+            .or_default()
+            .push((page, path));
+    }
+    chapters
+}
+
+// Decode cached pages, in order, along with the pages that failed to decode.
+// Every page decoded is a step of `progress`.
+fn decode_pages(
+    cached: Cached,
+    progress: &dyn ProgressSink,
+) -> (Vec<DynamicImage>, Vec<FailedPage>) {
+    let decoded = cached
+        .into_par_iter()
+        .map(|(page, path)| {
+            let img = std::fs::read(path)
+                .map_err(Error::from)
+                .and_then(|bytes| Ok(image::load_from_memory(&bytes)?));
+            progress.event(Event::Step { item: page.stem() });
+            (page, img)
+        })
+        .collect::<Vec<_>>();
+
+    let mut images = Vec::new();
+    let mut failed = Vec::new();
+    for (page, img) in decoded {
+        match img {
+            Ok(img) => images.push(img),
+            Err(error) => failed.push(FailedPage { page, error }),
+        }
+    }
+    (images, failed)
+}
+
+// Lay the cached pages out, the new pages being cached in "layout" next to them.
+// Along with the pages that failed to decode.
+// Only one chapter is decoded at a time, so memory stays flat however long the series is.
+fn lay_out_cache(
+    cached: Cached,
+    manga: &Manga,
+    options: &DownloadOptions,
+    layout: ChapterLayout,
+) -> Result<(Cached, Vec<FailedPage>)> {
+    if layout.is_none() {
+        return Ok((cached, Vec::new()));
+    }
+    let progress = options.progress.as_ref();
+    progress.event(Event::Phase {
+        phase: Phase::Decode,
+        total: cached.len(),
+    });
+    let dir = options.output.cache_dir(manga).join("layout");
+    std::fs::create_dir_all(&dir)?;
+    let quality = options.pipeline.quality.unwrap_or(DEFAULT_QUALITY);
+    let mut laid_out = Vec::new();
+    let mut failed = Vec::new();
+    for (_, cached) in by_chapter(cached) {
+        // Laid out pages keep the format of the first page of their chapter, and its volume.
+        let Some((first, first_path)) = cached.first().cloned() else {
+            continue;
+        };
+        let format = options.pipeline.format.unwrap_or_else(|| {
+            first_path
+                .extension()
+                .and_then(|ext| PageFormat::from_extension(&ext.to_string_lossy()))
+                .unwrap_or(PageFormat::Png)
+        });
+        let (decoded, unreadable) = decode_pages(cached, progress);
+        failed.extend(unreadable);
+        let pages = layout
+            .apply(decoded)
+            .into_par_iter()
+            .enumerate()
+            .map(|(i, image)| {
+                // A whole chapter can be too high for jpeg and webp.
+                let format = if image.height() > format.max_height() {
+                    PageFormat::Png
+                } else {
+                    format
+                };
+                let page = Page {
+                    number: i + 1,
+                    ..first.clone()
+                };
+                let path = dir.join(format!("{}.{}", page.stem(), format.extension()));
+                std::fs::write(&path, encode(&image, format, quality)?)?;
+                Ok((page, path))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        laid_out.extend(pages);
+    }
+    Ok((laid_out, failed))
+}
+
+// Copy the cached pages to where the images modes save them, in a folder per chapter or all in one.
+pub fn save_images(
+    cached: &[(Page, PathBuf)],
+    manga: &Manga,
+    output: &Output,
+    folder: bool,
+    progress: &dyn ProgressSink,
+) -> Result<()> {
+    progress.event(Event::Phase {
+        phase: Phase::Write,
+        total: cached.len(),
+    });
+    cached.par_iter().try_for_each(|(page, cached)| {
+        let ext = cached.extension().unwrap_or_default().to_string_lossy();
+        let path = output.page_path(manga, page, folder, &ext);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(cached, &path)?;
+        progress.event(Event::FileWritten { path });
+        Ok(())
+    })
+}
+
+// Download the raw images of pages into the cache directory of the manga, sorted by page.
+// Keeping them on disk means a crash while assembling the output does not lose the download.
+// A page split in several parts is listed once per part.
+pub async fn download_to_cache(
+    source: Arc<dyn Source>,
+    pages: Vec<Page>,
+    manga: &Manga,
+    options: &DownloadOptions,
+    scheduler: &Scheduler,
+) -> Result<(Cached, Vec<FailedPage>)> {
+    spool_to_cache(source, pages, manga, options, scheduler, None).await
+}
+
+// Pages cached on disk, along with where.
+type Cached = Vec<(Page, PathBuf)>;
+
+// A page done downloading, `paths` being where its parts were cached or `None` when it failed.
+struct Spooled {
+    page: Page,
+    paths: Option<Vec<PathBuf>>,
+}
+
+// Same as `download_to_cache`, also reporting every page to `spooled` as soon as it is done.
+async fn spool_to_cache(
+    source: Arc<dyn Source>,
+    pages: Vec<Page>,
+    manga: &Manga,
+    options: &DownloadOptions,
+    scheduler: &Scheduler,
+    spooled: Option<UnboundedSender<Spooled>>,
+) -> Result<(Cached, Vec<FailedPage>)> {
+    let DownloadOptions {
+        output,
+        progress,
+        pipeline,
+        ..
+    } = options;
+    progress.event(Event::Phase {
+        phase: Phase::Download,
+        total: pages.len(),
+    });
+    let tracker = Arc::new(ChapterTracker::new(progress.clone(), &pages));
+    let manifest = Arc::new(Mutex::new(Manifest::load(&output.manga_dir(manga))?));
+    let cache_dir = output.cache_dir(manga);
+    fs::create_dir_all(&cache_dir).await?;
+
+    let results = scheduler
+        .run(pages, |page| {
+            let source = source.clone();
+            let pipeline = pipeline.clone();
+            let manifest = manifest.clone();
+            let cache_dir = cache_dir.clone();
+            let spooled = spooled.clone();
+            let tracker = tracker.clone();
+            async move {
+                tracker.started(&page);
+                let path_for = |ext: &str| cache_dir.join(format!("{}.{ext}", page.stem()));
+
+                // Only downloaded when a previous run did not already.
+                let fetched = match resumed(&manifest, &page, path_for).await {
+                    Some((paths, size)) => {
+                        tracker.done(&page, size, true);
+                        Ok(paths)
                     }
+                    None => match fetch_page(source.as_ref(), &pipeline, &page.url).await {
+                        Ok(parts) => {
+                            let size = parts_size(&parts);
+                            let paths = write_page(&manifest, &page, path_for, parts).await?;
+                            tracker.done(&page, size, false);
+                            Ok(paths)
+                        }
+                        Err(error) => {
+                            tracker.failed(&page, &error);
+                            Err(error)
+                        }
+                    },
                 };
-                images.insert(url.split('/').last().unwrap().to_string(), img);
+
+                if let Some(spooled) = &spooled {
+                    // The receiver only goes away when it failed itself, which is reported by it.
+                    let _ = spooled.send(Spooled {
+                        page: page.clone(),
+                        paths: fetched.as_ref().ok().cloned(),
+                    });
+                }
+                Ok::<_, Error>(match fetched {
+                    Ok(paths) => Ok(paths
+                        .into_iter()
+                        .map(|path| (page.clone(), path))
+                        .collect::<Vec<_>>()),
+                    Err(error) => Err(FailedPage { page, error }),
+                })
             }
-            images
-        });
-        handles.push(handle);
-    }
-    let mut images = BTreeMap::new();
-    // Wait for all threads to finish.
-    for handle in handles {
-        images.extend(handle.await.unwrap());
+        })
+        .await;
+
+    let mut cached = Vec::new();
+    let mut failed = Vec::new();
+    for result in results {
+        match result? {
+            Ok(parts) => cached.extend(parts),
+            Err(page) => failed.push(page),
+        }
     }
-    images
+    save_manifest(&manifest).await?;
+    // Stable, so the parts of a page stay in order.
+    cached.par_sort_by_key(|(page, _)| page.stem());
+    Ok((cached, failed))
 }
 
-pub fn save_to_pdf(images: BTreeMap<String, DynamicImage>, manga: &Manga, unicode: bool) {
-    println!("Adding images to a pdf...");
-    let out_file = File::create(format!("{}.pdf", manga.i)).unwrap();
-
-    let m = MultiProgress::new();
+pub fn save_to_pdf(
+    images: BTreeMap<String, DynamicImage>,
+    manga: &Manga,
+    output: &Output,
+    page_size: Option<PageSize>,
+    progress: &dyn ProgressSink,
+) -> Result<()> {
+    progress.event(Event::Phase {
+        phase: Phase::Write,
+        total: 1,
+    });
+    let path = output.series_path(manga, "pdf");
+    let out_file = create_file(&path)?;
 
-    let sty = if unicode {
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .unwrap()
-    } else {
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+    let pdf = ImageToPdf::default()
+        .add_images_par(
+            images
+                .into_par_iter()
+                .map(|(_, img)| fit_page(img, page_size)),
         )
-        .expect("Failed to create progress style")
-        .progress_chars("#>-")
-    };
+        .set_document_title(path.display().to_string())
+        .create();
+
+    pdf.save(&mut BufWriter::new(out_file))
+        .map_err(|e| Error::Pdf(e.to_string()))?;
+    progress.event(Event::FileWritten { path });
+    Ok(())
+}
 
+// Download pages and write the pdf of every chapter as soon as all of its pages arrived.
+// Only the chapter being written is ever decoded, so memory stays flat however long the series is.
+pub async fn download_to_pdf_split_chapters(
+    source: Arc<dyn Source>,
+    pages: Vec<Page>,
+    manga: &Manga,
+    options: &DownloadOptions,
+    layout: ChapterLayout,
+    scheduler: &Scheduler,
+) -> Result<Vec<FailedPage>> {
+    let page_size = options.page_size;
+    let mut remaining = BTreeMap::new();
+    for page in &pages {
+        *remaining.entry(page.chapter.clone()).or_insert(0_usize) += 1;
+    }
+
+    let (sender, mut receiver) = unbounded_channel::<Spooled>();
+    let writer_manga = manga.clone();
+    let writer_output = options.output.clone();
+    let writer_progress = options.progress.clone();
+    let writer = tokio::spawn(async move {
+        let mut arrived = BTreeMap::new();
+        let mut failed = Vec::new();
+        while let Some(Spooled { page, paths }) = receiver.recv().await {
+            let chapter = page.chapter.clone();
+            if let Some(paths) = paths {
+                arrived
+                    .entry(chapter.clone())
+                    .or_insert_with(Vec::new)
+                    .extend(paths.into_iter().map(|path| (page.clone(), path)));
+            }
+
+            let left = remaining
+                .get_mut(&chapter)
+                .expect("Page of a chapter that was not requested");
+            *left -= 1;
+            if *left > 0 {
+                continue;
+            }
+            let Some(cached) = arrived.remove(&chapter) else {
+                continue;
+            };
+            let manga = writer_manga.clone();
+            let output = writer_output.clone();
+            let progress = writer_progress.clone();
+            failed.extend(
+                tokio::task::spawn_blocking(move || {
+                    write_chapter_pdf(
+                        &manga,
+                        &output,
+                        &chapter,
+                        cached,
+                        page_size,
+                        layout,
+                        progress.as_ref(),
+                    )
+                })
+                .await
+                .expect("Pdf task panicked")?,
+            );
+        }
+        Ok::<_, Error>(failed)
+    });
+
+    let (_, mut failed) =
+        spool_to_cache(source, pages, manga, options, scheduler, Some(sender)).await?;
+    failed.extend(writer.await.expect("Pdf writer task panicked")?);
+    Ok(failed)
+}
+
+// Write the pdf of one chapter from its cached pages, returning the pages that failed to decode.
+fn write_chapter_pdf(
+    manga: &Manga,
+    output: &Output,
+    chapter: &str,
+    mut cached: Vec<(Page, PathBuf)>,
+    page_size: Option<PageSize>,
+    layout: ChapterLayout,
+    progress: &dyn ProgressSink,
+) -> Result<Vec<FailedPage>> {
+    cached.par_sort_by_key(|(page, _)| page.stem());
+    let volume = cached
+        .first()
+        .map(|(page, _)| page.volume.clone())
+        .unwrap_or_default();
+    // Decoded while the pages are still downloading, which are the steps shown.
+    let (images, failed) = decode_pages(cached, &Silent);
+    if images.is_empty() {
+        return Ok(failed);
+    }
+
+    let path = output.chapter_path(manga, chapter, &volume, "pdf");
+    let out_file = create_file(&path)?;
     let pdf = ImageToPdf::default()
-        .add_images_par(images.into_par_iter().map(|(_, img)| img))
-        .set_document_title(format!("{}.pdf", manga.i))
-        .create_with_progress(sty, m);
+        .add_images_par(
+            layout
+                .apply(images)
+                .into_par_iter()
+                .map(|img| fit_page(img, page_size)),
+        )
+        .set_document_title(path.display().to_string())
+        .create();
+    pdf.save(&mut BufWriter::new(out_file))
+        .map_err(|e| Error::Pdf(e.to_string()))?;
+    progress.event(Event::FileWritten { path });
+    Ok(failed)
+}
 
-    println!("Saving to file...");
-    pdf.save(&mut BufWriter::new(out_file)).unwrap();
+pub fn save_to_cbz(
+    cached: &[(Page, PathBuf)],
+    manga: &Manga,
+    output: &Output,
+    page_size: Option<PageSize>,
+    progress: &dyn ProgressSink,
+) -> Result<()> {
+    progress.event(Event::Phase {
+        phase: Phase::Write,
+        total: 1,
+    });
+    let images = cached
+        .iter()
+        .map(|(_, path)| path.clone())
+        .collect::<Vec<_>>();
+    let path = output.series_path(manga, "cbz");
+    write_cbz(
+        &path,
+        &ComicInfo::series(manga, images.len()),
+        &images,
+        page_size,
+    )?;
+    progress.event(Event::FileWritten { path });
+    Ok(())
 }
 
-pub fn save_to_pdf_split_chapters(
-    images: BTreeMap<String, DynamicImage>,
+pub fn save_to_cbz_split_chapters(
+    cached: &[(Page, PathBuf)],
     manga: &Manga,
-    unicode: bool,
-) {
-    println!("Adding images to pdfs...");
+    output: &Output,
+    infos: &[ChapterInfo],
+    page_size: Option<PageSize>,
+    progress: &dyn ProgressSink,
+) -> Result<()> {
     let mut images_split = BTreeMap::new();
-    for (i, img) in images {
-        let chapter = i.split('-').next().unwrap();
+    for (page, path) in cached {
         images_split
-            .entry(chapter.to_string())
-            .or_insert_with(Vec::new)
-            .push(img);
+            .entry(page.chapter.clone())
+            .or_insert_with(|| (page.volume.clone(), Vec::new()))
+            .1
+            .push(path.clone());
     }
-    let sty = if unicode {
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+    progress.event(Event::Phase {
+        phase: Phase::Write,
+        total: images_split.len(),
+    });
+
+    images_split
+        .into_par_iter()
+        .try_for_each(|(chapter, (volume, images))| {
+            let info = infos.iter().find(|info| info.to_url_id() == chapter);
+            let path = output.chapter_path(manga, &chapter, &volume, "cbz");
+            write_cbz(
+                &path,
+                &ComicInfo::chapter(manga, &chapter, info, images.len()),
+                &images,
+                page_size,
+            )?;
+            progress.event(Event::FileWritten { path });
+            Ok(())
+        })
+}
+
+pub fn save_to_epub(
+    cached: &[(Page, PathBuf)],
+    manga: &Manga,
+    output: &Output,
+    page_size: Option<PageSize>,
+    progress: &dyn ProgressSink,
+) -> Result<()> {
+    progress.event(Event::Phase {
+        phase: Phase::Write,
+        total: 1,
+    });
+    let mut chapters: Vec<EpubChapter> = Vec::new();
+    for (page, path) in cached {
+        let title = chapter_name(&page.chapter);
+        match chapters.last_mut() {
+            Some(chapter) if chapter.title == title => chapter.images.push(path.clone()),
+            _ => chapters.push(EpubChapter {
+                title,
+                images: vec![path.clone()],
+            }),
+        }
+    }
+    let path = output.series_path(manga, "epub");
+    write_epub(&path, manga, &chapters, page_size)?;
+    progress.event(Event::FileWritten { path });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::FakeSource;
+
+    fn manga() -> Manga {
+        Manga {
+            i: "Fake-Manga".to_string(),
+            s: "Fake Manga".to_string(),
+            ..Manga::default()
+        }
+    }
+
+    fn chapter(encoded: &str) -> Chapter {
+        Chapter {
+            Chapter: encoded.to_string(),
+            Page: String::new(),
+            Directory: String::new(),
+        }
+    }
+
+    fn png() -> Vec<u8> {
+        encode(
+            &DynamicImage::new_rgb8(4, 6),
+            PageFormat::Png,
+            DEFAULT_QUALITY,
         )
         .unwrap()
-    } else {
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .expect("Failed to create progress style")
-        .progress_chars("#>-")
-    };
+    }
 
-    println!("Saving to file...");
-    let progress_bar = ProgressBar::new(images_split.keys().len() as u64).with_style(sty);
+    // Two chapters of two pages, the last page of the second one missing.
+    fn source() -> FakeSource {
+        let manga = manga();
+        FakeSource::new()
+            .with_manga(manga.clone())
+            .with_chapter(&manga, chapter("100010"), &["fake://1-1", "fake://1-2"])
+            .with_chapter(&manga, chapter("100020"), &["fake://2-1", "fake://2-2"])
+            .with_image("fake://1-1", png())
+            .with_image("fake://1-2", png())
+            .with_image("fake://2-1", png())
+    }
 
-    images_split.into_par_iter().for_each(|(chapter, images)| {
-        let out_file = File::create(format!("{}-{}.pdf", manga.i, chapter)).unwrap();
+    // A folder of its own for each test, emptied first.
+    fn options(test: &str, save_type: SaveType) -> DownloadOptions {
+        let dir = std::env::temp_dir().join(format!("mangadl-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        DownloadOptions {
+            save_type,
+            download_type: DownloadType::Multi,
+            threads: Thread::default(),
+            output: Output::new(dir),
+            progress: Arc::new(Silent),
+            pipeline: Pipeline::default(),
+            page_size: None,
+        }
+    }
 
-        let pdf = ImageToPdf::default()
-            .add_images_par(images.into_par_iter())
-            .set_document_title(format!("{}-{}.pdf", manga.i, chapter))
-            .create();
+    async fn download(source: &Arc<FakeSource>, options: &DownloadOptions) -> Vec<FailedPage> {
+        let chapters = source.chapters(&manga()).await.unwrap();
+        download_manga(source.clone(), manga(), chapters, options)
+            .await
+            .unwrap()
+    }
 
-        pdf.save(&mut BufWriter::new(out_file)).unwrap();
-        progress_bar.inc(1);
-    });
+    #[tokio::test]
+    async fn images_are_saved_and_resumed() {
+        let source = Arc::new(source());
+        let options = options("images", SaveType::ImagesChapter);
+        let output = &options.output;
+
+        let failed = download(&source, &options).await;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].page.url, "fake://2-2");
+        for (chapter, page) in [("0001", 1), ("0001", 2), ("0002", 1)] {
+            let page = Page {
+                chapter: chapter.to_string(),
+                volume: "1".to_string(),
+                number: page,
+                url: String::new(),
+            };
+            let path = output.page_path(&manga(), &page, true, "png");
+            assert_eq!(std::fs::read(&path).unwrap(), png(), "{}", path.display());
+        }
+        assert_eq!(source.fetched().len(), 4);
+
+        // Only the missing page is asked for again.
+        let failed = download(&source, &options).await;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(source.fetched()[4..], ["fake://2-2"]);
+        std::fs::remove_dir_all(&output.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn url_list_fetches_no_images() {
+        let source = Arc::new(source());
+        let options = options("urls", SaveType::Urls);
+        let output = &options.output;
+
+        assert!(download(&source, &options).await.is_empty());
+        let urls = std::fs::read_to_string(output.series_path(&manga(), "txt")).unwrap();
+        assert_eq!(urls, "fake://1-1\nfake://1-2\nfake://2-1\nfake://2-2\n");
+        assert!(source.fetched().is_empty());
+        std::fs::remove_dir_all(&output.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn single_cbz_holds_every_page() {
+        let source = Arc::new(source());
+        let options = options("cbz", SaveType::CbzSingle);
+        let output = &options.output;
+
+        assert_eq!(download(&source, &options).await.len(), 1);
+        let file = std::fs::File::open(output.series_path(&manga(), "cbz")).unwrap();
+        let archive = zip::ZipArchive::new(file).unwrap();
+        let images = archive
+            .file_names()
+            .filter(|name| name.ends_with(".png"))
+            .count();
+        assert_eq!(images, 3);
+        std::fs::remove_dir_all(&output.dir).unwrap();
+    }
 }
@@ -0,0 +1,297 @@
+use clap::ValueEnum;
+use std::{
+    cmp::Reverse,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crate::{
+    error::{Error, Result},
+    manga::Manga,
+};
+
+// Order of the filtered catalogue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortBy {
+    // Most recently updated first.
+    Latest,
+    // Alphabetical.
+    Title,
+    // Most recently released first.
+    Year,
+}
+
+impl Display for SortBy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortBy::Latest => write!(f, "Latest update"),
+            SortBy::Title => write!(f, "Title"),
+            SortBy::Year => write!(f, "Year"),
+        }
+    }
+}
+
+// Years of release, both ends included, e.g. "2010-2015", "2010-", "-2015" or "2012".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct YearRange {
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+}
+
+impl YearRange {
+    #[must_use]
+    pub fn contains(&self, year: &str) -> bool {
+        if *self == YearRange::default() {
+            return true;
+        }
+        // Manga without a known year only pass when no years are asked for.
+        let Ok(year) = year.trim().parse::<u32>() else {
+            return false;
+        };
+        self.from.is_none_or(|from| from <= year) && self.to.is_none_or(|to| year <= to)
+    }
+}
+
+impl FromStr for YearRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let year = |year: &str| match year.trim() {
+            "" => Ok(None),
+            year => year
+                .parse::<u32>()
+                .map(Some)
+                .map_err(|_| Error::Years(s.to_string())),
+        };
+        let range = match s.split_once('-') {
+            None => {
+                let year = year(s)?;
+                YearRange {
+                    from: year,
+                    to: year,
+                }
+            }
+            Some((from, to)) => YearRange {
+                from: year(from)?,
+                to: year(to)?,
+            },
+        };
+        match range {
+            YearRange {
+                from: Some(from),
+                to: Some(to),
+            } if from > to => Err(Error::Years(s.to_string())),
+            YearRange {
+                from: None,
+                to: None,
+            } => Err(Error::Years(s.to_string())),
+            range => Ok(range),
+        }
+    }
+}
+
+impl Display for YearRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from == to => write!(f, "{from}"),
+            (from, to) => write!(
+                f,
+                "{}-{}",
+                from.map(|from| from.to_string()).unwrap_or_default(),
+                to.map(|to| to.to_string()).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+// Narrows down and orders the catalogue, e.g.
+// Filter::new().with_type("Manhwa").without_genres(vec!["Horror".into()]).sorted_by(SortBy::Latest)
+// Text values match ignoring case, and every condition set has to hold.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    genres: Vec<String>,
+    any_genres: Vec<String>,
+    excluded_genres: Vec<String>,
+    scan_status: Option<String>,
+    publish_status: Option<String>,
+    kind: Option<String>,
+    years: YearRange,
+    hot: bool,
+    sort: Option<SortBy>,
+}
+
+impl Filter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Keep manga having all of `genres`.
+    #[must_use]
+    pub fn with_genres(mut self, genres: Vec<String>) -> Self {
+        self.genres.extend(genres);
+        self
+    }
+
+    // Keep manga having at least one of `genres`.
+    #[must_use]
+    pub fn with_any_genre(mut self, genres: Vec<String>) -> Self {
+        self.any_genres.extend(genres);
+        self
+    }
+
+    // Drop manga having any of `genres`.
+    #[must_use]
+    pub fn without_genres(mut self, genres: Vec<String>) -> Self {
+        self.excluded_genres.extend(genres);
+        self
+    }
+
+    // Keep manga whose scanlation is e.g. "Ongoing" or "Complete".
+    #[must_use]
+    pub fn with_scan_status(mut self, status: impl Into<String>) -> Self {
+        self.scan_status = Some(status.into());
+        self
+    }
+
+    // Keep manga whose publication is e.g. "Ongoing", "Complete" or "Hiatus".
+    #[must_use]
+    pub fn with_publish_status(mut self, status: impl Into<String>) -> Self {
+        self.publish_status = Some(status.into());
+        self
+    }
+
+    // Keep manga of the type, e.g. "Manga", "Manhwa", "Manhua" or "One-shot".
+    #[must_use]
+    pub fn with_type(mut self, kind: impl Into<String>) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_years(mut self, years: YearRange) -> Self {
+        self.years = years;
+        self
+    }
+
+    // Keep only the manga the site marks as hot.
+    #[must_use]
+    pub fn hot_only(mut self) -> Self {
+        self.hot = true;
+        self
+    }
+
+    #[must_use]
+    pub fn sorted_by(mut self, sort: SortBy) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    #[must_use]
+    pub fn matches(&self, manga: &Manga) -> bool {
+        let has = |genre: &String| manga.g.iter().any(|g| g.eq_ignore_ascii_case(genre));
+        let is = |wanted: &Option<String>, value: &str| {
+            wanted
+                .as_ref()
+                .is_none_or(|wanted| wanted.eq_ignore_ascii_case(value))
+        };
+        self.genres.iter().all(has)
+            && (self.any_genres.is_empty() || self.any_genres.iter().any(has))
+            && !self.excluded_genres.iter().any(has)
+            && is(&self.scan_status, &manga.ss)
+            && is(&self.publish_status, &manga.ps)
+            && is(&self.kind, &manga.t)
+            && self.years.contains(&manga.y)
+            && (!self.hot || manga.h)
+    }
+
+    // The manga matching every condition, in the order asked for or as given when there is none.
+    #[must_use]
+    pub fn apply(&self, manga: Vec<Manga>) -> Vec<Manga> {
+        let mut manga = manga
+            .into_iter()
+            .filter(|manga| self.matches(manga))
+            .collect::<Vec<_>>();
+        match self.sort {
+            None => {}
+            Some(SortBy::Latest) => manga.sort_by_key(|manga| Reverse(manga.lt)),
+            Some(SortBy::Title) => manga.sort_by_cached_key(|manga| manga.s.to_lowercase()),
+            Some(SortBy::Year) => {
+                manga.sort_by_key(|manga| Reverse(manga.y.trim().parse::<u32>().ok()));
+            }
+        }
+        manga
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn years(s: &str) -> YearRange {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn year_ranges() {
+        assert_eq!(
+            years("2015"),
+            YearRange {
+                from: Some(2015),
+                to: Some(2015)
+            }
+        );
+        assert_eq!(
+            years(" 2010 - 2015 "),
+            YearRange {
+                from: Some(2010),
+                to: Some(2015)
+            }
+        );
+        assert_eq!(
+            years("2010-"),
+            YearRange {
+                from: Some(2010),
+                to: None
+            }
+        );
+        assert_eq!(
+            years("-2015"),
+            YearRange {
+                from: None,
+                to: Some(2015)
+            }
+        );
+        for s in ["2015", "2010-2015", "2010-", "-2015"] {
+            assert_eq!(years(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn invalid_year_ranges() {
+        for invalid in ["", "-", "2015-2010", "twenty", "2010-x", "1-2-3"] {
+            assert!(invalid.parse::<YearRange>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn years_contained() {
+        let range = years("2010-2015");
+        assert!(range.contains("2010"));
+        assert!(range.contains(" 2015 "));
+        assert!(!range.contains("2009"));
+        assert!(!range.contains("2016"));
+        assert!(years("2010-").contains("2024"));
+        assert!(years("-2015").contains("1990"));
+        assert!(!years("-2015").contains("2016"));
+    }
+
+    #[test]
+    fn unknown_years_only_pass_without_a_range() {
+        assert!(YearRange::default().contains(""));
+        assert!(YearRange::default().contains("unknown"));
+        assert!(!years("2010-").contains(""));
+        assert!(!years("2010-").contains("unknown"));
+    }
+}
@@ -1,5 +1,25 @@
 pub mod args;
+pub mod cbz;
 pub mod chapter;
+pub mod config;
+pub mod crop;
+pub mod epub;
+pub mod error;
 pub mod fetch;
+pub mod filter;
+pub mod library;
 pub mod manga;
+pub mod manifest;
+pub mod output;
+pub mod pipeline;
+pub mod profile;
+pub mod progress;
+pub mod range;
+pub mod retry;
+pub mod scheduler;
+pub mod search;
+pub mod source;
+pub mod store;
+pub mod strip;
+pub mod time;
 pub mod types;
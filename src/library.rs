@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    chapter::Chapter,
+    error::Result,
+    manga::Manga,
+    range::{ChapterNumber, ChapterSelection},
+    store::{read_if_exists, write_atomic},
+    time::utc_now,
+    types::SaveType,
+};
+
+const FILE_NAME: &str = "library.json";
+
+// A downloaded chapter, and where it was saved to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChapterEntry {
+    pub downloaded_at: String,
+    pub save_type: SaveType,
+    pub output: PathBuf,
+    #[serde(default)]
+    pub read: bool,
+}
+
+// A followed manga and its downloaded chapters, keyed by url id.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Series {
+    pub id: String,
+    pub title: String,
+    // How chapters of the series were last saved.
+    pub save_type: SaveType,
+    #[serde(default)]
+    pub chapters: BTreeMap<String, ChapterEntry>,
+}
+
+impl Series {
+    // Url ids of the chapters picked by `selection`.
+    #[must_use]
+    pub fn select(&self, selection: &ChapterSelection) -> Vec<String> {
+        let ids = self.chapters.keys().cloned().collect::<Vec<_>>();
+        selection.select_by(&ids, |id| id.parse::<ChapterNumber>().unwrap_or_default())
+    }
+
+    // The `chapters` never downloaded, or whose output is gone from the disk.
+    #[must_use]
+    pub fn missing(&self, chapters: &[Chapter]) -> Vec<Chapter> {
+        chapters
+            .iter()
+            .filter(|chapter| {
+                !self
+                    .chapters
+                    .get(&chapter.to_url_id())
+                    .is_some_and(|entry| entry.output.exists())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+// Every manga downloaded so far, kept as json in the data directory of the user.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Library {
+    pub series: BTreeMap<String, Series>,
+    #[serde(skip)]
+    file: PathBuf,
+}
+
+impl Library {
+    // Where the library is kept, e.g. "~/.local/share/mangadl-rs/library.json" on linux.
+    #[must_use]
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_default()
+            .join("mangadl-rs")
+            .join(FILE_NAME)
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path())
+    }
+
+    // Load the library kept in `file`, starting an empty one if there is none yet.
+    pub fn load_from(file: &Path) -> Result<Self> {
+        let mut library = match read_if_exists(file, fs::read)? {
+            Some(bytes) => serde_json::from_slice(&bytes)?,
+            None => Library::default(),
+        };
+        library.file = file.to_path_buf();
+        Ok(library)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_atomic(&self.file, &serde_json::to_vec_pretty(self)?)
+    }
+
+    // Remember that `chapter` of `manga` was saved to `output`, following the manga if it was not yet.
+    pub fn record(&mut self, manga: &Manga, chapter: &str, save_type: &SaveType, output: PathBuf) {
+        let series = self
+            .series
+            .entry(manga.i.clone())
+            .or_insert_with(|| Series {
+                id: manga.i.clone(),
+                title: manga.s.clone(),
+                save_type: save_type.clone(),
+                chapters: BTreeMap::new(),
+            });
+        series.title = manga.s.clone();
+        series.save_type = save_type.clone();
+
+        // Downloading a chapter again does not make it unread.
+        let read = series
+            .chapters
+            .get(chapter)
+            .is_some_and(|chapter| chapter.read);
+        series.chapters.insert(
+            chapter.to_string(),
+            ChapterEntry {
+                downloaded_at: utc_now(),
+                save_type: save_type.clone(),
+                output,
+                read,
+            },
+        );
+    }
+
+    // Mark the selected chapters of the manga `id` as read or unread.
+    // Returns how many chapters were marked, `None` when the manga is not in the library.
+    pub fn set_read(
+        &mut self,
+        id: &str,
+        selection: &ChapterSelection,
+        read: bool,
+    ) -> Option<usize> {
+        let series = self.series.get_mut(id)?;
+        let selected = series.select(selection);
+        for chapter in &selected {
+            if let Some(entry) = series.chapters.get_mut(chapter) {
+                entry.read = read;
+            }
+        }
+        Some(selected.len())
+    }
+
+    // Forget the selected chapters of the manga `id`, or the whole manga when there is no selection.
+    // Downloaded files are left alone. Returns `None` when the manga is not in the library.
+    pub fn remove(&mut self, id: &str, selection: Option<&ChapterSelection>) -> Option<Series> {
+        let Some(selection) = selection else {
+            return self.series.remove(id);
+        };
+        let series = self.series.get_mut(id)?;
+        let mut removed = Series {
+            chapters: BTreeMap::new(),
+            ..series.clone()
+        };
+        for chapter in series.select(selection) {
+            if let Some(entry) = series.chapters.remove(&chapter) {
+                removed.chapters.insert(chapter, entry);
+            }
+        }
+        Some(removed)
+    }
+}
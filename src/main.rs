@@ -1,112 +1,398 @@
+use clap::Parser;
 use crossterm::style::Stylize;
-use std::time::Duration;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use inquire::{
     ui::{Color, RenderConfig, StyleSheet, Styled},
-    CustomType, InquireError, MultiSelect, Select,
+    CustomType, InquireError, MultiSelect, Select, Text,
 };
 use mangadl_rs::{
-    args::{display_help, get_encoding, Encoding},
+    args::{
+        Cli, Command, DownloadArgs, Encoding, LibraryCommand, SearchArgs, UpdateArgs, WatchArgs,
+    },
     chapter::Chapter,
-    fetch,
+    config::Config,
+    fetch::{self, DownloadOptions, FailedPage},
+    filter::{Filter, SortBy, YearRange},
+    library::{Library, Series},
     manga::Manga,
+    output::Output,
+    progress::{self, ProgressSink},
+    range::{ChapterNumber, ChapterSelection},
+    scheduler::DEFAULT_PER_HOST,
+    search::Query,
+    source::{CacheMode, CachedSource, MangaseeSource, Source},
     types::{DownloadType, SaveType, Thread},
 };
 use tokio::time::sleep;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = std::env::args().collect::<Vec<_>>();
-    let encoding = get_encoding(&args);
-    if display_help(&args) {
-        return Ok(());
-    }
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cli = Cli::parse();
+    let encoding = cli.encoding();
 
     if encoding == Encoding::Unicode {
         inquire::set_global_render_config(get_render_config());
     }
 
-    let future_manga = tokio::spawn(async move {
-        loop {
-            match Manga::all_manga_list().await {
-                Ok(manga) => break manga,
-                Err(e) => {
-                    eprintln!("Error: {}, Retrying!", e);
-                    std::thread::sleep(Duration::from_millis(100));
-                }
-            }
-        }
-    });
-
-    let save_type = loop {
-        match Select::new(
-            "How would you like to save?",
-            vec![
-                SaveType::PdfSplit,
-                SaveType::PdfSingle,
-                SaveType::Images,
-                SaveType::ImagesChapter,
-                SaveType::Urls,
-            ],
-        )
-        .with_formatter(&|savetype| format! {"{:?}", savetype.value})
-        .prompt()
-        {
-            Ok(k) => break k,
-            Err(InquireError::OperationInterrupted) => return Ok(()),
-            Err(_) => eprintln!("{}", "Please select an option.".red().slow_blink()),
-        }
+    let progress = progress::renderer(cli.progress, encoding == Encoding::Unicode);
+
+    let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(output_dir) = cli.output_dir {
+        config.set_output_dir(output_dir);
+    }
+    let per_host = cli
+        .connections_per_host
+        .map(usize::from)
+        .or(config.connections_per_host)
+        .unwrap_or(DEFAULT_PER_HOST);
+    let mut mangasee = MangaseeSource::new()
+        .with_retry(config.retry_policy(cli.retries))
+        .with_progress(progress.clone())
+        .with_host_limit(per_host);
+    if let Some(user_agent) = &config.user_agent {
+        mangasee = mangasee.with_user_agent(user_agent)?;
+    }
+    // Watching compares the latest chapters of the catalogue, which have to be current.
+    let mode = if cli.offline {
+        CacheMode::Offline
+    } else if cli.refresh || matches!(cli.command, Some(Command::Watch(_))) {
+        CacheMode::Refresh
+    } else {
+        CacheMode::Normal
     };
+    let source: Arc<dyn Source> = Arc::new(
+        CachedSource::new(mangasee)
+            .with_ttl(config.catalogue_ttl())
+            .with_mode(mode)
+            .with_progress(progress.clone()),
+    );
 
-    let download_type = match save_type {
-        SaveType::Urls => DownloadType::Single,
-        SaveType::ImagesChapter | SaveType::Images | SaveType::PdfSingle | SaveType::PdfSplit => {
-            loop {
-                match Select::new(
-                    "How would you like to download?",
-                    vec![DownloadType::Single, DownloadType::Multi],
-                )
-                .prompt()
-                {
-                    Ok(ans) => break ans,
-                    Err(InquireError::OperationInterrupted) => return Ok(()),
-                    Err(_) => eprintln!("{}", "Please select an option.".red().slow_blink()),
-                }
+    match cli.command {
+        None => wizard(source, &config, &progress).await,
+        Some(Command::Search(args)) => search(source.as_ref(), args).await,
+        Some(Command::Info { id }) => info(source.as_ref(), &id).await,
+        Some(Command::Chapters { id }) => chapters(source.as_ref(), &id).await,
+        Some(Command::Download(args)) => download(source, &config, args, &progress).await,
+        Some(Command::Update(args)) => update(source, &config, args, &progress).await,
+        Some(Command::Watch(args)) => watch(source, &config, args, &progress).await,
+        Some(Command::Library { command }) => library(command),
+    }
+}
+
+async fn find_manga(
+    source: &dyn Source,
+    id: &str,
+) -> Result<Manga, Box<dyn std::error::Error + Send + Sync>> {
+    source
+        .catalogue()
+        .await?
+        .into_iter()
+        .find(|manga| manga.i == id)
+        .ok_or_else(|| format!("No manga with the id {id} found").into())
+}
+
+async fn search(
+    source: &dyn Source,
+    args: SearchArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut manga = source.catalogue().await?;
+    if let Some(query) = &args.query {
+        manga = Manga::search(query, manga);
+    }
+    // Sorting keeps the best matches first among equals.
+    for manga in args.filter().apply(manga) {
+        println!("{}\t{}", manga.i, manga);
+    }
+    Ok(())
+}
+
+async fn info(
+    source: &dyn Source,
+    id: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let manga = find_manga(source, id).await?;
+    println!("{}", manga.s.as_str().blue());
+    println!("Id:           {}", manga.i);
+    if !manga.al.is_empty() {
+        println!("Also known as: {}", manga.al.join(", "));
+    }
+    println!("Authors:      {}", manga.a.join(", "));
+    println!("Genres:       {}", manga.g.join(", "));
+    println!("Type:         {}", manga.t);
+    println!("Year:         {}", manga.y);
+    println!("Status:       {} (scan), {} (publish)", manga.ss, manga.ps);
+    println!(
+        "Latest:       {}",
+        ChapterNumber::display_id(&Chapter::decode_id(&manga.l)?)
+    );
+    Ok(())
+}
+
+async fn chapters(
+    source: &dyn Source,
+    id: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let manga = find_manga(source, id).await?;
+    for chapter in source.chapters(&manga).await? {
+        println!("{}\t{} pages", chapter, chapter.Page);
+    }
+    Ok(())
+}
+
+async fn download(
+    source: Arc<dyn Source>,
+    config: &Config,
+    args: DownloadArgs,
+    progress: &Arc<dyn ProgressSink>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let manga = find_manga(source.as_ref(), &args.id).await?;
+    let chapters = args.chapters.select(&source.chapters(&manga).await?);
+    if chapters.is_empty() {
+        return Err(format!("No chapter of {} matches {}", manga.i, args.chapters).into());
+    }
+
+    let options = args
+        .defaults()
+        .or(&config.for_series(&manga.i))
+        .into_options(&config.templates, progress.clone());
+    let failed = fetch::download_manga(source, manga.clone(), chapters.clone(), &options).await?;
+
+    record_download(&manga, &chapters, &options, &failed)?;
+    report_failed(failed)
+}
+
+async fn update(
+    source: Arc<dyn Source>,
+    config: &Config,
+    args: UpdateArgs,
+    progress: &Arc<dyn ProgressSink>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let library = Library::load()?;
+    if let Some(unknown) = args.ids.iter().find(|id| !library.series.contains_key(*id)) {
+        return Err(format!("{unknown} is not in the library").into());
+    }
+    let catalogue = source.catalogue().await?;
+
+    let mut summary = Vec::new();
+    for series in library
+        .series
+        .values()
+        .filter(|series| args.ids.is_empty() || args.ids.contains(&series.id))
+    {
+        say(progress.as_ref(), series.title.as_str().blue());
+        let result =
+            update_series(source.clone(), config, &catalogue, series, &args, progress).await;
+        summary.push((series, result));
+    }
+
+    say(progress.as_ref(), format!("{}", "Summary".blue()));
+    let mut failures = 0;
+    for (series, result) in summary {
+        match result {
+            Ok(new) if new.is_empty() => {
+                say(progress.as_ref(), format!("{}\tup to date", series.id))
+            }
+            Ok(new) => say(
+                progress.as_ref(),
+                format!(
+                    "{}\t{} new chapter(s): {}",
+                    series.id,
+                    new.len(),
+                    new.join(", ")
+                ),
+            ),
+            Err(e) => {
+                failures += 1;
+                say(
+                    progress.as_ref(),
+                    format!("{}\t{} {}", series.id, "failed:".red(), e),
+                );
             }
         }
+    }
+    if failures > 0 {
+        return Err(format!("{failures} manga failed to update").into());
+    }
+    Ok(())
+}
+
+// Download the chapters of `series` missing from the disk, returning their ids.
+async fn update_series(
+    source: Arc<dyn Source>,
+    config: &Config,
+    catalogue: &[Manga],
+    series: &Series,
+    args: &UpdateArgs,
+    progress: &Arc<dyn ProgressSink>,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let manga = catalogue
+        .iter()
+        .find(|manga| manga.i == series.id)
+        .cloned()
+        .ok_or_else(|| format!("{} is no longer in the catalogue", series.id))?;
+    let chapters = source.chapters(&manga).await?;
+    let missing = series.missing(&chapters);
+    if missing.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut options = args
+        .defaults()
+        .or(&config.for_series(&series.id))
+        .into_options(&config.templates, progress.clone());
+    // Saved the way the manga was saved before, unless the config says otherwise for this manga.
+    options.save_type = config
+        .series
+        .get(&series.id)
+        .and_then(|overrides| overrides.save.clone())
+        .unwrap_or_else(|| series.save_type.clone());
+
+    // The file has to be written again as a whole, the pages already downloaded come from the cache.
+    let chapters = if options.save_type.is_whole_series() {
+        chapters
+    } else {
+        missing.clone()
     };
+    let failed = fetch::download_manga(source, manga.clone(), chapters.clone(), &options).await?;
 
-    let mut treads: Thread = Thread::new(1).unwrap();
+    record_download(&manga, &chapters, &options, &failed)?;
+    report_failed(failed)?;
+    Ok(missing.iter().map(Chapter::to_url_id).collect())
+}
 
-    if let SaveType::Urls = save_type {
-        treads = loop {
-            match CustomType::new("Number of Threads: ")
-                .with_error_message("Please type a valid number")
-                .with_help_message("Type the amount of threads you want to use")
-                .prompt()
-            {
-                Ok(ans) => break ans,
-                Err(InquireError::OperationInterrupted) => return Ok(()),
-                Err(_) => eprintln!("{}", "Please enter amount of threads".red().slow_blink()),
+async fn watch(
+    source: Arc<dyn Source>,
+    config: &Config,
+    args: WatchArgs,
+    progress: &Arc<dyn ProgressSink>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Latest chapter and its release time of every followed manga, as of the previous check.
+    let mut seen = HashMap::new();
+    loop {
+        if let Err(e) = watch_once(source.clone(), config, &args.update, progress, &mut seen).await
+        {
+            eprintln!("{} {}", "Error:".red(), e);
+        }
+        say(
+            progress.as_ref(),
+            format!("Next check in {} minute(s)", args.interval),
+        );
+        sleep(Duration::from_secs(args.interval * 60)).await;
+    }
+}
+
+// Check the catalogue once, updating the followed manga whose latest chapter changed and is not downloaded yet.
+async fn watch_once(
+    source: Arc<dyn Source>,
+    config: &Config,
+    args: &UpdateArgs,
+    progress: &Arc<dyn ProgressSink>,
+    seen: &mut HashMap<String, (String, i128)>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Read again every time, manga may have been downloaded or removed in the meantime.
+    let library = Library::load()?;
+    // The catalogue carries the latest chapter of every manga, so a single request covers them all.
+    let catalogue = source.catalogue().await?;
+
+    for series in library
+        .series
+        .values()
+        .filter(|series| args.ids.is_empty() || args.ids.contains(&series.id))
+    {
+        let Some(manga) = catalogue.iter().find(|manga| manga.i == series.id) else {
+            continue;
+        };
+        let latest = (manga.l.clone(), manga.lt);
+        if seen.get(&series.id) == Some(&latest) {
+            continue;
+        }
+
+        let latest_id = match Chapter::decode_id(&manga.l) {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("{}\t{} {}", series.id, "failed:".red(), e);
+                continue;
             }
         };
+        if !series.chapters.contains_key(&latest_id) {
+            say(progress.as_ref(), series.title.as_str().blue());
+            match update_series(source.clone(), config, &catalogue, series, args, progress).await {
+                Ok(new) => say(
+                    progress.as_ref(),
+                    format!(
+                        "{}\t{} new chapter(s): {}",
+                        series.id,
+                        new.len(),
+                        new.join(", ")
+                    ),
+                ),
+                // Not marked as seen, so it is tried again on the next check.
+                Err(e) => {
+                    eprintln!("{}\t{} {}", series.id, "failed:".red(), e);
+                    continue;
+                }
+            }
+        }
+        seen.insert(series.id.clone(), latest);
     }
+    Ok(())
+}
 
-    if let DownloadType::Multi = download_type {
-        treads = loop {
-            match CustomType::new("Number of Threads: ")
-                .with_error_message("Please type a valid number")
-                .with_help_message("Type the amount of threads you want to use")
-                .prompt()
-            {
-                Ok(ans) => break ans,
-                Err(InquireError::OperationInterrupted) => return Ok(()),
-                Err(_) => eprintln!("{}", "Please enter amount of threads".red().slow_blink()),
+fn library(command: LibraryCommand) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut library = Library::load()?;
+    match command {
+        LibraryCommand::List { id: None } => {
+            for series in library.series.values() {
+                let read = series.chapters.values().filter(|entry| entry.read).count();
+                println!(
+                    "{}\t{}\t{}/{} read\t{:?}",
+                    series.id,
+                    series.title,
+                    read,
+                    series.chapters.len(),
+                    series.save_type
+                );
             }
-        };
+        }
+        LibraryCommand::List { id: Some(id) } => {
+            let series = library
+                .series
+                .get(&id)
+                .ok_or_else(|| format!("{id} is not in the library"))?;
+            for (chapter, entry) in &series.chapters {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    chapter,
+                    if entry.read { "read  " } else { "unread" },
+                    entry.downloaded_at,
+                    entry.output.display()
+                );
+            }
+        }
+        LibraryCommand::Read { id, chapters } => mark_read(&mut library, &id, &chapters, true)?,
+        LibraryCommand::Unread { id, chapters } => mark_read(&mut library, &id, &chapters, false)?,
+        LibraryCommand::Remove { id, chapters } => {
+            let removed = library
+                .remove(&id, chapters.as_ref())
+                .ok_or_else(|| format!("{id} is not in the library"))?;
+            library.save()?;
+            println!(
+                "Removed {} chapter(s) of {}",
+                removed.chapters.len(),
+                removed.id
+            );
+        }
     }
+    Ok(())
+}
 
-    let manga = future_manga.await.unwrap();
+async fn wizard(
+    source: Arc<dyn Source>,
+    config: &Config,
+    progress: &Arc<dyn ProgressSink>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let manga = source.catalogue().await?;
 
     let genres =
         loop {
@@ -132,44 +418,162 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         };
 
-    let ans = if let Some(..) = genres {
-        let selection_manga = Manga::filter_manga(genres.unwrap(), manga);
-        if selection_manga.is_none() || selection_manga.clone().unwrap().is_empty() {
-            println!(
-                "{}",
-                "No manga with such genres found, closing program"
-                    .blue()
-                    .rapid_blink()
-            );
-            sleep(Duration::from_secs(3)).await;
-            return Ok(());
+    let filter = match more_filters(
+        &manga,
+        Filter::new().with_genres(genres.unwrap_or_default()),
+    ) {
+        Ok(filter) => filter,
+        Err(InquireError::OperationInterrupted) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let manga = filter.apply(manga);
+    if manga.is_empty() {
+        println!(
+            "{}",
+            "No manga with such filters found, closing program"
+                .blue()
+                .rapid_blink()
+        );
+        sleep(Duration::from_secs(3)).await;
+        return Ok(());
+    }
+
+    let manga = loop {
+        match Text::new("Search Manga: ")
+            .with_help_message("Title, alternative title or author, esc to browse all")
+            .prompt_skippable()
+        {
+            Ok(Some(query)) if !query.trim().is_empty() => {
+                // A chosen order takes precedence over the best matches.
+                let found = filter.apply(Manga::search(&query, manga.clone()));
+                if found.is_empty() {
+                    eprintln!("{}", "No manga matched, try again".red().slow_blink());
+                    continue;
+                }
+                break found;
+            }
+            Ok(_) => break manga,
+            Err(InquireError::OperationInterrupted) => return Ok(()),
+            Err(_) => continue,
         }
-        loop {
-            match Select::new("Select Manga", selection_manga.as_ref().unwrap().clone()).prompt() {
-                Ok(ans) => break ans,
+    };
+
+    let filter = |input: &str, manga: &Manga, _: &str, _: usize| {
+        manga.match_score(&Query::new(input)).is_some()
+    };
+    let ans = loop {
+        match Select::new("Select Manga", manga.clone())
+            .with_filter(&filter)
+            .prompt()
+        {
+            Ok(ans) => break ans,
+            Err(InquireError::OperationInterrupted) => return Ok(()),
+            Err(_) => eprintln!("{}", "Please select manga".red().slow_blink()),
+        }
+    };
+
+    // Fetched while the remaining questions are answered.
+    let chapters_source = source.clone();
+    let series_manga = ans.clone();
+    let future_chapters =
+        tokio::spawn(async move { chapters_source.chapters(&series_manga).await });
+
+    // Values set in the config for the manga are preselected, or not asked for at all with skip-prompts.
+    let defaults = config.for_series(&ans.i).apply_profile();
+
+    let save_types = vec![
+        SaveType::PdfSplit,
+        SaveType::PdfSingle,
+        SaveType::CbzSplit,
+        SaveType::CbzSingle,
+        SaveType::Epub,
+        SaveType::Images,
+        SaveType::ImagesChapter,
+        SaveType::Urls,
+    ];
+    let save_type = match &defaults.save {
+        Some(save_type) if config.skip_prompts => save_type.clone(),
+        configured => loop {
+            let cursor = configured
+                .as_ref()
+                .and_then(|configured| save_types.iter().position(|save| save == configured))
+                .unwrap_or_default();
+            match Select::new("How would you like to save?", save_types.clone())
+                .with_starting_cursor(cursor)
+                .with_formatter(&|savetype| format! {"{:?}", savetype.value})
+                .prompt()
+            {
+                Ok(k) => break k,
                 Err(InquireError::OperationInterrupted) => return Ok(()),
-                Err(_) => eprintln!("{}", "Please select manga".red().slow_blink()),
+                Err(_) => eprintln!("{}", "Please select an option.".red().slow_blink()),
             }
-        }
-    } else {
-        loop {
-            match Select::new("Select Manga", manga.clone()).prompt() {
+        },
+    };
+
+    let download_type = match (&save_type, defaults.download) {
+        (SaveType::Urls, _) => DownloadType::Single,
+        (_, Some(download_type)) if config.skip_prompts => download_type,
+        (_, configured) => loop {
+            let cursor = usize::from(configured == Some(DownloadType::Multi));
+            match Select::new(
+                "How would you like to download?",
+                vec![DownloadType::Single, DownloadType::Multi],
+            )
+            .with_starting_cursor(cursor)
+            .prompt()
+            {
                 Ok(ans) => break ans,
                 Err(InquireError::OperationInterrupted) => return Ok(()),
-                Err(_) => eprintln!("{}", "Please select manga".red().slow_blink()),
+                Err(_) => eprintln!("{}", "Please select an option.".red().slow_blink()),
             }
-        }
+        },
     };
 
+    let mut treads: Thread = defaults.threads.unwrap_or_default();
+
+    let wants_threads = save_type == SaveType::Urls || download_type == DownloadType::Multi;
+    if wants_threads && !(config.skip_prompts && defaults.threads.is_some()) {
+        treads = loop {
+            let mut prompt = CustomType::new("Number of Threads: ")
+                .with_error_message("Please type a valid number")
+                .with_help_message("Type the amount of threads you want to use");
+            if let Some(threads) = defaults.threads {
+                prompt = prompt.with_default(threads);
+            }
+            match prompt.prompt() {
+                Ok(ans) => break ans,
+                Err(InquireError::OperationInterrupted) => return Ok(()),
+                Err(_) => eprintln!("{}", "Please enter amount of threads".red().slow_blink()),
+            }
+        };
+    }
+
+    let chapter_list = future_chapters.await.expect("Chapters task panicked")?;
     let chapters = loop {
-        if let Ok(chapters) = MultiSelect::new(
-            "Select Chapters",
-            Chapter::list(&ans.i, &ans.l)
-                .await
-                .expect("Network Error, try again later."),
-        )
-        .prompt()
+        // Typing a range is quicker than toggling hundreds of chapters.
+        match Text::new("Chapters:")
+            .with_help_message("e.g. 1-50,72,100.5-,latest:5 or all, esc to pick from a list")
+            .prompt_skippable()
         {
+            Ok(Some(ranges)) => match ranges.parse::<ChapterSelection>() {
+                Ok(selection) => {
+                    let chapters = selection.select(&chapter_list);
+                    if chapters.is_empty() {
+                        eprintln!("{}", "No chapter matches".red().slow_blink());
+                        continue;
+                    }
+                    break chapters;
+                }
+                Err(e) => {
+                    eprintln!("{}", e.to_string().red().slow_blink());
+                    continue;
+                }
+            },
+            Ok(None) => {}
+            Err(InquireError::OperationInterrupted) => return Ok(()),
+            Err(_) => continue,
+        }
+        if let Ok(chapters) = MultiSelect::new("Select Chapters", chapter_list.clone()).prompt() {
             if chapters.is_empty() {
                 eprintln!(
                     "{}",
@@ -182,19 +586,187 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         eprintln!("{}", "Please select a chapter".red().slow_blink());
     };
 
-    fetch::download_manga(
-        ans,
-        chapters,
+    let options = DownloadOptions {
         save_type,
         download_type,
-        treads,
-        encoding == Encoding::Unicode,
-    )
-    .await;
+        threads: treads,
+        output: defaults
+            .output_dir
+            .map_or_else(Output::default, Output::new)
+            .with_templates(config.templates.clone()),
+        progress: progress.clone(),
+        pipeline: defaults.images,
+        page_size: defaults.profile.map(|profile| profile.page_size()),
+    };
+    let failed = fetch::download_manga(source, ans.clone(), chapters.clone(), &options).await?;
+
+    record_download(&ans, &chapters, &options, &failed)?;
+    report_failed(failed)
+}
 
+fn mark_read(
+    library: &mut Library,
+    id: &str,
+    chapters: &ChapterSelection,
+    read: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let marked = library
+        .set_read(id, chapters, read)
+        .ok_or_else(|| format!("{id} is not in the library"))?;
+    library.save()?;
+    println!(
+        "Marked {marked} chapter(s) as {}",
+        if read { "read" } else { "unread" }
+    );
     Ok(())
 }
 
+// Further narrow down the catalogue, every filter being optional.
+fn more_filters(manga: &[Manga], mut filter: Filter) -> Result<Filter, InquireError> {
+    const OPTIONS: [&str; 8] = [
+        "Any of genres",
+        "Exclude genres",
+        "Type",
+        "Scan status",
+        "Publish status",
+        "Years",
+        "Hot only",
+        "Sort by",
+    ];
+    let Some(chosen) = MultiSelect::new("More Filters", OPTIONS.to_vec())
+        .with_help_message("esc to skip, ↑↓ to move, space to select one, → to all, ← to none")
+        .prompt_skippable()?
+    else {
+        return Ok(filter);
+    };
+
+    let genres = Manga::find_all_genre(manga);
+    for option in chosen {
+        match option {
+            "Any of genres" => {
+                if let Some(genres) =
+                    MultiSelect::new("Any of Genre(s)", genres.clone()).prompt_skippable()?
+                {
+                    filter = filter.with_any_genre(genres);
+                }
+            }
+            "Exclude genres" => {
+                if let Some(genres) =
+                    MultiSelect::new("Exclude Genre(s)", genres.clone()).prompt_skippable()?
+                {
+                    filter = filter.without_genres(genres);
+                }
+            }
+            "Type" => {
+                if let Some(kind) =
+                    Select::new("Type", distinct(manga, |manga| &manga.t)).prompt_skippable()?
+                {
+                    filter = filter.with_type(kind);
+                }
+            }
+            "Scan status" => {
+                if let Some(status) = Select::new("Scan Status", distinct(manga, |manga| &manga.ss))
+                    .prompt_skippable()?
+                {
+                    filter = filter.with_scan_status(status);
+                }
+            }
+            "Publish status" => {
+                if let Some(status) =
+                    Select::new("Publish Status", distinct(manga, |manga| &manga.ps))
+                        .prompt_skippable()?
+                {
+                    filter = filter.with_publish_status(status);
+                }
+            }
+            "Years" => {
+                if let Some(years) = CustomType::<YearRange>::new("Years: ")
+                    .with_error_message("Please type a valid year range")
+                    .with_help_message("e.g. 2010-2015, 2010-, -2015 or 2012")
+                    .prompt_skippable()?
+                {
+                    filter = filter.with_years(years);
+                }
+            }
+            "Hot only" => filter = filter.hot_only(),
+            "Sort by" => {
+                if let Some(sort) =
+                    Select::new("Sort by", vec![SortBy::Latest, SortBy::Title, SortBy::Year])
+                        .prompt_skippable()?
+                {
+                    filter = filter.sorted_by(sort);
+                }
+            }
+            _ => unreachable!("not a filter option"),
+        }
+    }
+    Ok(filter)
+}
+
+// Every value of `field` in the catalogue, e.g. all the types.
+fn distinct(manga: &[Manga], field: impl Fn(&Manga) -> &String) -> Vec<String> {
+    let mut values = manga
+        .iter()
+        .map(field)
+        .filter(|value| !value.is_empty())
+        .cloned()
+        .collect::<Vec<_>>();
+    values.sort();
+    values.dedup();
+    values
+}
+
+// Add the chapters that downloaded without a failed page to the library.
+fn record_download(
+    manga: &Manga,
+    chapters: &[Chapter],
+    options: &DownloadOptions,
+    failed: &[FailedPage],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let save_type = &options.save_type;
+    // A list of urls is not a download.
+    if *save_type == SaveType::Urls {
+        return Ok(());
+    }
+    let mut library = Library::load()?;
+    for chapter in chapters {
+        let id = chapter.to_url_id();
+        if failed.iter().any(|failed| failed.page.chapter == id) {
+            continue;
+        }
+        let output = options.output.path(manga, chapter, save_type);
+        library.record(manga, &id, save_type, output);
+    }
+    library.save()?;
+    Ok(())
+}
+
+// A line for people, on stderr when stdout carries events for other programs.
+fn say(progress: &dyn ProgressSink, line: impl std::fmt::Display) {
+    if progress.owns_stdout() {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+// List the pages that could not be downloaded, failing the run if there are any.
+fn report_failed(failed: Vec<FailedPage>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    for FailedPage { page, error } in &failed {
+        eprintln!(
+            "{} chapter {} page {}: {}",
+            "Failed".red(),
+            page.chapter,
+            page.number,
+            error
+        );
+    }
+    Err(format!("{} page(s) failed to download", failed.len()).into())
+}
+
 fn get_render_config() -> RenderConfig {
     let mut render_config = RenderConfig::default();
     render_config.prompt_prefix = Styled::new("?").with_fg(Color::DarkGreen);
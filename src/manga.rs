@@ -1,20 +1,22 @@
-use brotlic::DecompressorReader;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use rayon::slice::ParallelSliceMut;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
     fmt::{Display, Formatter},
-    io::{BufReader, Read},
+    sync::Arc,
 };
 
-use crate::{chapter::Chapter, types::Thread};
-
-const URL: &str = "https://mangasee123.com/";
+use crate::{
+    chapter::{Chapter, Page},
+    error::{Error, Result},
+    progress::{Event, Phase, ProgressSink},
+    scheduler::Scheduler,
+    search::Query,
+    source::Source,
+};
 
 // Names taken directly from mangasee123, rename was deemed unnecessary.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Manga {
     pub i: String,
     pub s: String,
@@ -35,149 +37,94 @@ pub struct Manga {
 }
 
 impl Manga {
-    pub async fn chapters_urls(
+    pub async fn chapters_pages(
         &self,
-        threads: Thread,
-        unicode: bool,
+        source: Arc<dyn Source>,
+        scheduler: &Scheduler,
+        progress: &Arc<dyn ProgressSink>,
         chapters: Vec<Chapter>,
-    ) -> Vec<String> {
-        // Set progress bar
-        let m = MultiProgress::new();
-
-        let sty = if unicode {
-            ProgressStyle::with_template(
-                "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-            )
-            .unwrap()
-        } else {
-            ProgressStyle::with_template(
-                "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-            )
-            .expect("Failed to create progress style")
-            .progress_chars("#>-")
-        };
-
-        let mut urls = Vec::new();
-
-        // Split urls into <threads> parts.
-        let mut chapters_split = Vec::new();
-        for _ in 0..threads.get() {
-            chapters_split.push(Vec::new());
-        }
-        for (i, url) in chapters.iter().enumerate() {
-            chapters_split[i % threads.get() as usize].push(url.clone());
-        }
-
-        let mut chapter_for_progress = chapters_split.clone();
-
-        let mut progress_bars = Vec::new();
-        progress_bars.push(
-            m.add(ProgressBar::new(
-                chapter_for_progress
-                    .pop()
-                    .expect("failed to get previous url")
-                    .len() as u64,
-            )),
-        );
-
-        for _ in 0..threads.get() - 1 {
-            progress_bars.push(
-                m.insert_after(
-                    &progress_bars
-                        .clone()
-                        .pop()
-                        .expect("failed to get previous bar to insert after"),
-                    ProgressBar::new(
-                        chapter_for_progress
-                            .pop()
-                            .expect("failed to get url len for bar")
-                            .len() as u64,
-                    ),
-                ),
-            );
-        }
-
-        for (i, bar) in progress_bars.iter().enumerate() {
-            bar.set_style(sty.clone());
-            bar.set_message(format!("Chapter's Urls Part {}", i + 1));
-        }
-
-        // Spawn a tread for each chunk
-        let mut handles = Vec::new();
-        for (chunk, bar) in chapters_split.into_iter().zip(progress_bars) {
-            let myself = self.clone();
-            let chunk = chunk.clone();
-            let handle = tokio::spawn(async move {
-                {
-                    let mut chunk_urls = Vec::new();
-
-                    for chapter in chunk {
-                        bar.inc(1);
-                        let url = chapter.cur_path_name(myself.i.as_str()).await;
-                        for page in 1..chapter.Page.parse::<usize>().unwrap() {
-                            chunk_urls.push(format!(
-                                "https://{}/manga/{}{}/{:0>4}-{:0>3}.png",
+    ) -> Result<Vec<Page>> {
+        progress.event(Event::Phase {
+            phase: Phase::Urls,
+            total: chapters.len(),
+        });
+
+        let results = scheduler
+            .run(chapters, |chapter| {
+                let myself = self.clone();
+                let source = source.clone();
+                let progress = progress.clone();
+                async move {
+                    let urls = source.page_urls(&myself, &chapter).await?;
+                    progress.event(Event::Step {
+                        item: chapter.to_url_id(),
+                    });
+                    Ok::<_, Error>(
+                        urls.into_iter()
+                            .enumerate()
+                            .map(|(i, url)| Page {
+                                chapter: chapter.to_url_id(),
+                                volume: chapter.volume(),
+                                number: i + 1,
                                 url,
-                                &myself.i,
-                                chapter.directory(),
-                                chapter.to_url_id(),
-                                page
-                            ));
-                        }
-                    }
-                    chunk_urls
+                            })
+                            .collect::<Vec<_>>(),
+                    )
                 }
-            });
-            handles.push(handle);
-        }
+            })
+            .await;
 
-        // Join all the handles
-        for handle in handles {
-            let mut one_chapters_urls = handle.await.unwrap();
-            urls.append(&mut one_chapters_urls);
+        let mut pages = Vec::new();
+        for result in results {
+            pages.extend(result?);
         }
-        urls.par_sort();
-        urls
+        pages.par_sort_by_key(Page::stem);
+        Ok(pages)
     }
 
-    pub async fn all_manga_list() -> Result<Vec<Manga>, Box<dyn std::error::Error>> {
-        let bin = reqwest::Client::new()
-            .get(format!("{URL}search/").as_str())
-            .header("Accept-Encoding", "br")
-            .send()
-            .await?
-            .bytes()
-            .await?;
+    // How well the manga matches `query`, by its title, alternative titles or authors.
+    #[must_use]
+    pub fn match_score(&self, query: &Query) -> Option<u32> {
+        let title = query.score(&self.s);
+        let alternatives = self.al.iter().filter_map(|al| query.score(al)).max();
+        let authors = self.a.iter().filter_map(|a| query.score(a)).max();
+        [
+            title,
+            alternatives.map(|score| score * 9 / 10),
+            authors.map(|score| score * 7 / 10),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
 
-        // Convert the brotli compressed bytes to a string
-        let mut reader = BufReader::new(bin.as_ref());
-        let mut bytes = Vec::new();
-        let mut brotli = DecompressorReader::new(&mut reader);
-        brotli.read_to_end(&mut bytes)?;
-        let page = String::from_utf8(bytes)?;
+    // The manga matching `query`, best match first.
+    // Ignores case and accents, and allows for typos and abbreviations.
+    #[must_use]
+    pub fn search(query: &str, manga: Vec<Manga>) -> Vec<Manga> {
+        let query = Query::new(query);
+        let mut ranked = manga
+            .into_iter()
+            .filter_map(|manga| manga.match_score(&query).map(|score| (score, manga)))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(a, _), (b, _)| b.cmp(a));
+        ranked.into_iter().map(|(_, manga)| manga).collect()
+    }
 
-        Ok(serde_json::from_str(
-            Regex::new(r#"vm\.Directory = (.*);"#)
-                .expect("Failed to create regex")
-                .captures(&page)
-                .expect("Failed to find manga list")
-                .get(1)
-                .expect("Failed to get manga list")
-                .as_str(),
-        )
-        .unwrap())
+    // Whether the pages are cuts of one long vertical strip, as manhwa and manhua usually are.
+    #[must_use]
+    pub fn is_long_strip(&self) -> bool {
+        matches!(self.t.as_str(), "Manhwa" | "Manhua")
     }
 
+    // Whether the pages are read from right to left, as japanese manga are.
+    // Manhwa, manhua and original english language comics read from left to right.
     #[must_use]
-    pub fn filter_manga(genres: Vec<String>, manga: Vec<Manga>) -> Option<Vec<Manga>> {
-        let mut filtered_manga = manga;
-        for genre in genres {
-            filtered_manga.retain(|manga| manga.g.contains(&genre));
-        }
-        Some(filtered_manga)
+    pub fn is_right_to_left(&self) -> bool {
+        !matches!(self.t.as_str(), "Manhwa" | "Manhua" | "OEL")
     }
 
-    pub fn find_all_genre(manga: &Vec<Manga>) -> Vec<String> {
+    pub fn find_all_genre(manga: &[Manga]) -> Vec<String> {
         let mut genres: Vec<String> = Vec::new();
         for m in manga {
             for g in &m.g {
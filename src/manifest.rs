@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::Result,
+    store::{read_if_exists, write_atomic},
+};
+
+const FILE_NAME: &str = ".mangadl.json";
+
+// Save to disk after this many new pages, so a crash loses little without rewriting the file for every page.
+const SAVE_EVERY: usize = 25;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PageEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    // The files after the first, for a page that was split into several.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<PageEntry>,
+}
+
+impl PageEntry {
+    // Entry of a page saved to the `files`, each with its content, the first being the page itself.
+    #[must_use]
+    pub fn new<B: AsRef<[u8]>>(files: &[(PathBuf, B)]) -> Option<Self> {
+        let mut entries = files.iter().map(|(path, bytes)| PageEntry {
+            path: path.clone(),
+            size: bytes.as_ref().len() as u64,
+            sha256: hash(bytes.as_ref()),
+            parts: Vec::new(),
+        });
+        let mut entry = entries.next()?;
+        entry.parts = entries.collect();
+        Some(entry)
+    }
+
+    // Whether the page was saved to `path_for` the extension of its file.
+    // The extension depends on the image format, so it is only known once the page was downloaded.
+    #[must_use]
+    pub fn is_at(&self, path_for: impl Fn(&str) -> PathBuf) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|ext| path_for(&ext.to_string_lossy()) == self.path)
+    }
+
+    // The files of the page, if they are all still the ones that were saved.
+    // Every file is read and hashed, so this is best kept off async tasks and out of locks.
+    #[must_use]
+    pub fn intact_paths(&self) -> Option<Vec<PathBuf>> {
+        (self.is_intact() && self.parts.iter().all(PageEntry::is_intact)).then(|| {
+            std::iter::once(&self.path)
+                .chain(self.parts.iter().map(|part| &part.path))
+                .cloned()
+                .collect()
+        })
+    }
+
+    // Size of every file of the page.
+    #[must_use]
+    pub fn total_size(&self) -> u64 {
+        self.size + self.parts.iter().map(|part| part.size).sum::<u64>()
+    }
+
+    // Whether the file is still the one that was saved.
+    fn is_intact(&self) -> bool {
+        match fs::read(&self.path) {
+            Ok(bytes) => bytes.len() as u64 == self.size && hash(&bytes) == self.sha256,
+            Err(_) => false,
+        }
+    }
+}
+
+// Record of every page already downloaded for a manga, keyed by page url.
+// Lets an interrupted download pick up where it stopped.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Manifest {
+    pub pages: BTreeMap<String, PageEntry>,
+    // Fingerprint of the image processing the pages went through, empty for untouched pages.
+    #[serde(default)]
+    pub processing: String,
+    #[serde(skip)]
+    file: PathBuf,
+    #[serde(skip)]
+    unsaved: usize,
+    // Snapshots taken, and the last of them written, so an older one never replaces a newer one.
+    #[serde(skip)]
+    snapshots: u64,
+    #[serde(skip)]
+    written: Arc<Mutex<u64>>,
+}
+
+// The manifest as it was at some point, to be written without holding on to the manifest.
+pub struct Snapshot {
+    file: PathBuf,
+    bytes: Vec<u8>,
+    number: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl Snapshot {
+    // Write the manifest, unless a newer snapshot was already written.
+    pub fn write(&self) -> Result<()> {
+        let mut written = self.written.lock().unwrap();
+        if *written >= self.number {
+            return Ok(());
+        }
+        write_atomic(&self.file, &self.bytes)?;
+        *written = self.number;
+        Ok(())
+    }
+}
+
+impl Manifest {
+    // Load the manifest kept in `dir`, starting a new one if there is none yet.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let file = dir.join(FILE_NAME);
+        let mut manifest = match read_if_exists(&file, fs::read)? {
+            Some(bytes) => serde_json::from_slice(&bytes)?,
+            None => Manifest::default(),
+        };
+        manifest.file = file;
+        Ok(manifest)
+    }
+
+    // The manifest as it is now, to write once the lock it is kept behind is let go of.
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        let bytes = serde_json::to_vec_pretty(self)?;
+        self.unsaved = 0;
+        self.snapshots += 1;
+        Ok(Snapshot {
+            file: self.file.clone(),
+            bytes,
+            number: self.snapshots,
+            written: self.written.clone(),
+        })
+    }
+
+    // Forget the pages processed differently than `processing` asks for, they have to be downloaded again.
+    // Returns whether anything changed.
+    pub fn set_processing(&mut self, processing: &str) -> bool {
+        if self.processing == processing {
+            return false;
+        }
+        self.pages.clear();
+        self.processing = processing.to_string();
+        true
+    }
+
+    // Remember that the page at `url` was saved as `entry`.
+    // Returns the manifest to write when enough pages were added since it last was.
+    pub fn record(&mut self, url: &str, entry: PageEntry) -> Result<Option<Snapshot>> {
+        self.pages.insert(url.to_string(), entry);
+        self.unsaved += 1;
+        if self.unsaved >= SAVE_EVERY {
+            return self.snapshot().map(Some);
+        }
+        Ok(None)
+    }
+}
+
+fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
@@ -0,0 +1,400 @@
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    chapter::{Chapter, Page},
+    error::{Error, Result},
+    manga::Manga,
+    range::ChapterNumber,
+    types::SaveType,
+};
+
+const PLACEHOLDERS: [&str; 9] = [
+    "id",
+    "series",
+    "series_slug",
+    "chapter",
+    "chapter_padded",
+    "volume",
+    "page",
+    "year",
+    "ext",
+];
+
+// Names of the saved files, relative to the output directory. "/" starts a folder.
+// Placeholders: {id}, {series}, {series_slug}, {chapter}, {chapter_padded}, {volume}, {page}, {year} and {ext}.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Templates {
+    // A file holding the whole series: single pdf, single cbz, epub and the url list.
+    pub series_file: String,
+    // A file holding one chapter: split pdfs and cbzs.
+    pub chapter_file: String,
+    // An image of the images mode.
+    pub image: String,
+    // An image of the images by chapter mode.
+    pub chapter_image: String,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            series_file: "{id}.{ext}".to_string(),
+            chapter_file: "{id}-{chapter_padded}.{ext}".to_string(),
+            image: "{id}/{chapter_padded}-{page}.{ext}".to_string(),
+            chapter_image: "{id}/{chapter_padded}/{page}.{ext}".to_string(),
+        }
+    }
+}
+
+impl Templates {
+    // Fail on placeholders that do not exist, rather than writing them out literally,
+    // and on templates writing outside of the output directory.
+    pub fn check(&self) -> Result<()> {
+        for template in [
+            &self.series_file,
+            &self.chapter_file,
+            &self.image,
+            &self.chapter_image,
+        ] {
+            if let Some(unknown) = placeholders(template).find(|name| !PLACEHOLDERS.contains(name))
+            {
+                return Err(Error::Template(unknown.to_string()));
+            }
+            if Path::new(template)
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+            {
+                return Err(Error::TemplatePath(template.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Where the files of a download are written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub dir: PathBuf,
+    pub templates: Templates,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new(".")
+    }
+}
+
+impl Output {
+    // Relative directories are resolved against the working directory,
+    // so the paths recorded in the library still point to the files when run from somewhere else.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let dir = match std::env::current_dir() {
+            Ok(cwd) if dir.is_relative() => cwd.join(dir),
+            _ => dir,
+        };
+        Self {
+            dir,
+            templates: Templates::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_templates(mut self, templates: Templates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    // Folder of the manga, holding the manifest and cache of its downloads.
+    #[must_use]
+    pub fn manga_dir(&self, manga: &Manga) -> PathBuf {
+        self.dir.join(&manga.i)
+    }
+
+    // Where the raw images of the pdf and cbz modes are kept until the output is written.
+    #[must_use]
+    pub fn cache_dir(&self, manga: &Manga) -> PathBuf {
+        self.manga_dir(manga).join(".cache")
+    }
+
+    // File holding the whole series.
+    #[must_use]
+    pub fn series_path(&self, manga: &Manga, ext: &str) -> PathBuf {
+        self.render(&self.templates.series_file, &Vars::series(manga, ext))
+    }
+
+    // File holding the chapter `chapter`, a url id, of `volume`.
+    #[must_use]
+    pub fn chapter_path(&self, manga: &Manga, chapter: &str, volume: &str, ext: &str) -> PathBuf {
+        let mut vars = Vars::series(manga, ext);
+        vars.chapter = chapter;
+        vars.volume = volume;
+        self.render(&self.templates.chapter_file, &vars)
+    }
+
+    // File of a single page, in a folder per chapter or all in one.
+    #[must_use]
+    pub fn page_path(&self, manga: &Manga, page: &Page, by_chapter: bool, ext: &str) -> PathBuf {
+        let mut vars = Vars::series(manga, ext);
+        vars.chapter = &page.chapter;
+        vars.volume = &page.volume;
+        vars.page = Some(page.number);
+        let template = if by_chapter {
+            &self.templates.chapter_image
+        } else {
+            &self.templates.image
+        };
+        self.render(template, &vars)
+    }
+
+    // Where `chapter` ends up for `save_type`, the folder of its images for the images modes.
+    #[must_use]
+    pub fn path(&self, manga: &Manga, chapter: &Chapter, save_type: &SaveType) -> PathBuf {
+        let chapter_id = chapter.to_url_id();
+        let volume = chapter.volume();
+        match save_type {
+            SaveType::Urls => self.series_path(manga, "txt"),
+            SaveType::PdfSingle => self.series_path(manga, "pdf"),
+            SaveType::CbzSingle => self.series_path(manga, "cbz"),
+            SaveType::Epub => self.series_path(manga, "epub"),
+            SaveType::PdfSplit => self.chapter_path(manga, &chapter_id, &volume, "pdf"),
+            SaveType::CbzSplit => self.chapter_path(manga, &chapter_id, &volume, "cbz"),
+            SaveType::Images | SaveType::ImagesChapter => {
+                let page = Page {
+                    chapter: chapter_id,
+                    volume,
+                    number: 1,
+                    url: String::new(),
+                };
+                let path =
+                    self.page_path(manga, &page, *save_type == SaveType::ImagesChapter, "png");
+                path.parent().map_or(path.clone(), PathBuf::from)
+            }
+        }
+    }
+
+    fn render(&self, template: &str, vars: &Vars) -> PathBuf {
+        let mut rendered = String::new();
+        let mut rest = template;
+        while let Some((before, after)) = rest.split_once('{') {
+            rendered.push_str(before);
+            let Some((name, after)) = after.split_once('}') else {
+                rest = after;
+                rendered.push('{');
+                break;
+            };
+            // An empty value, e.g. an unknown year, would turn "{year}/{series}" into "/Title",
+            // which is joined as an absolute path.
+            let value = sanitize(&vars.value(name));
+            rendered.push_str(if value.is_empty() { "_" } else { &value });
+            rest = after;
+        }
+        rendered.push_str(rest);
+        self.dir.join(rendered)
+    }
+}
+
+// Values of the placeholders, chapter ones being empty for a file holding the whole series.
+struct Vars<'a> {
+    manga: &'a Manga,
+    chapter: &'a str,
+    volume: &'a str,
+    page: Option<usize>,
+    ext: &'a str,
+}
+
+impl<'a> Vars<'a> {
+    fn series(manga: &'a Manga, ext: &'a str) -> Self {
+        Self {
+            manga,
+            chapter: "",
+            volume: "",
+            page: None,
+            ext,
+        }
+    }
+
+    fn value(&self, name: &str) -> String {
+        match name {
+            "id" => self.manga.i.clone(),
+            "series" => self.manga.s.clone(),
+            "series_slug" => slug(&self.manga.s),
+            // "0234.5" is chapter 234.5.
+            "chapter" => self
+                .chapter
+                .parse::<ChapterNumber>()
+                .map_or_else(|_| self.chapter.to_string(), |number| number.to_string()),
+            "chapter_padded" if self.chapter.is_empty() => String::new(),
+            "chapter_padded" => format!("{:0>4}", self.chapter),
+            "volume" => self.volume.to_string(),
+            "page" => self
+                .page
+                .map(|page| format!("{page:0>3}"))
+                .unwrap_or_default(),
+            "year" => self.manga.y.clone(),
+            "ext" => self.ext.to_string(),
+            // Rejected when the templates are loaded.
+            _ => String::new(),
+        }
+    }
+}
+
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+}
+
+// Replace the characters not allowed in file names on linux, macos or windows.
+fn sanitize(value: &str) -> String {
+    let sanitized = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    // Windows drops trailing dots and spaces, and ".." would leave the folder.
+    sanitized.trim().trim_end_matches('.').to_string()
+}
+
+// "Kaguya-sama: Love Is War" becomes "kaguya-sama-love-is-war".
+fn slug(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manga() -> Manga {
+        Manga {
+            i: "Kaguya-Wants-To-Be-Confessed-To".to_string(),
+            s: "Kaguya-sama: Love Is War?".to_string(),
+            y: "2015".to_string(),
+            ..Manga::default()
+        }
+    }
+
+    fn page(chapter: &str, number: usize) -> Page {
+        Page {
+            chapter: chapter.to_string(),
+            volume: "1".to_string(),
+            number,
+            url: String::new(),
+        }
+    }
+
+    fn output(templates: Templates) -> Output {
+        Output {
+            dir: PathBuf::from("out"),
+            templates,
+        }
+    }
+
+    #[test]
+    fn default_templates() {
+        let output = output(Templates::default());
+        let manga = manga();
+        assert_eq!(
+            output.series_path(&manga, "cbz"),
+            Path::new("out/Kaguya-Wants-To-Be-Confessed-To.cbz")
+        );
+        assert_eq!(
+            output.chapter_path(&manga, "0010.5", "1", "pdf"),
+            Path::new("out/Kaguya-Wants-To-Be-Confessed-To-0010.5.pdf")
+        );
+        assert_eq!(
+            output.page_path(&manga, &page("0010", 3), false, "jpg"),
+            Path::new("out/Kaguya-Wants-To-Be-Confessed-To/0010-003.jpg")
+        );
+        assert_eq!(
+            output.page_path(&manga, &page("0010", 3), true, "jpg"),
+            Path::new("out/Kaguya-Wants-To-Be-Confessed-To/0010/003.jpg")
+        );
+    }
+
+    #[test]
+    fn placeholders_are_filled() {
+        let output = output(Templates {
+            chapter_file: "{series_slug}/{year}/v{volume} c{chapter} {id}.{ext}".to_string(),
+            ..Templates::default()
+        });
+        assert_eq!(
+            output.chapter_path(&manga(), "0234.5", "2", "cbz"),
+            Path::new(
+                "out/kaguya-sama-love-is-war/2015/v2 c234.5 Kaguya-Wants-To-Be-Confessed-To.cbz"
+            )
+        );
+    }
+
+    #[test]
+    fn empty_values_stay_in_the_output_directory() {
+        let output = output(Templates {
+            series_file: "{year}/{series_slug}.{ext}".to_string(),
+            chapter_file: "{volume}/{chapter}.{ext}".to_string(),
+            ..Templates::default()
+        });
+        let manga = Manga {
+            y: String::new(),
+            ..manga()
+        };
+        let path = output.series_path(&manga, "cbz");
+        assert_eq!(path, Path::new("out/_/kaguya-sama-love-is-war.cbz"));
+        assert!(path.starts_with("out"));
+        assert_eq!(
+            output.chapter_path(&manga, "0001", "", "pdf"),
+            Path::new("out/_/1.pdf")
+        );
+    }
+
+    #[test]
+    fn illegal_characters_are_replaced() {
+        let output = output(Templates {
+            series_file: "{series}.{ext}".to_string(),
+            ..Templates::default()
+        });
+        assert_eq!(
+            output.series_path(&manga(), "epub"),
+            Path::new("out/Kaguya-sama_ Love Is War_.epub")
+        );
+        assert_eq!(sanitize("a/b\\c|d*e\n"), "a_b_c_d_e_");
+        assert_eq!(sanitize(" trailing. "), "trailing");
+        assert_eq!(sanitize(".."), "");
+
+        let manga = Manga {
+            s: "..".to_string(),
+            ..manga()
+        };
+        assert_eq!(output.series_path(&manga, "epub"), Path::new("out/_.epub"));
+    }
+
+    #[test]
+    fn check_rejects_bad_templates() {
+        assert!(Templates::default().check().is_ok());
+        let with_series_file = |series_file: &str| Templates {
+            series_file: series_file.to_string(),
+            ..Templates::default()
+        };
+        assert!(matches!(
+            with_series_file("{title}.{ext}").check(),
+            Err(Error::Template(name)) if name == "title"
+        ));
+        assert!(matches!(
+            with_series_file("../{id}.{ext}").check(),
+            Err(Error::TemplatePath(_))
+        ));
+        assert!(matches!(
+            with_series_file("/{id}.{ext}").check(),
+            Err(Error::TemplatePath(_))
+        ));
+    }
+}
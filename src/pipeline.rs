@@ -0,0 +1,414 @@
+use clap::ValueEnum;
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    ColorType, DynamicImage,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crop::{Crop, DEFAULT_LIMIT, DEFAULT_TOLERANCE},
+    error::{Error, Result},
+    strip::{LongStrip, Strip},
+};
+
+// Quality of jpeg and avif pages, when not configured.
+pub const DEFAULT_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+// Format pages are saved in. WebP is written lossless, AVIF needs the "avif" feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl PageFormat {
+    #[must_use]
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PageFormat::Png => "png",
+            PageFormat::Jpeg => "jpg",
+            PageFormat::Webp => "webp",
+            PageFormat::Avif => "avif",
+        }
+    }
+
+    // Format of a page saved with the extension `ext`.
+    #[must_use]
+    pub fn from_extension(ext: &str) -> Option<PageFormat> {
+        match ext.to_lowercase().as_str() {
+            "png" => Some(PageFormat::Png),
+            "jpg" | "jpeg" => Some(PageFormat::Jpeg),
+            "webp" => Some(PageFormat::Webp),
+            "avif" => Some(PageFormat::Avif),
+            _ => None,
+        }
+    }
+
+    // Highest image the format can hold, in pixels.
+    #[must_use]
+    pub fn max_height(&self) -> u32 {
+        match self {
+            PageFormat::Jpeg => 65_535,
+            PageFormat::Webp => 16_383,
+            PageFormat::Png | PageFormat::Avif => u32::MAX,
+        }
+    }
+
+    // Format of the raw bytes of a page, png for anything else.
+    #[must_use]
+    pub fn guess(bytes: &[u8]) -> PageFormat {
+        match image::guess_format(bytes) {
+            Ok(image::ImageFormat::Jpeg) => PageFormat::Jpeg,
+            Ok(image::ImageFormat::WebP) => PageFormat::Webp,
+            Ok(image::ImageFormat::Avif) => PageFormat::Avif,
+            _ => PageFormat::Png,
+        }
+    }
+}
+
+// What is done with double-page spreads, the pages wider than they are high.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Spreads {
+    Keep,
+    // Cut in two, one page after the other.
+    Split,
+    // Turned sideways to fill a portrait screen, the first page on top.
+    Rotate,
+}
+
+// Order the two pages of a spread are read in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadingOrder {
+    // Manga are read right to left.
+    #[default]
+    RightToLeft,
+    LeftToRight,
+}
+
+// Changes made to every page before it is saved, e.g. for e-ink readers
+//
+// [images]
+// max-width = 1072
+// max-height = 1448
+// grayscale = true
+// crop-margins = true
+// crop-limit = 10
+// spreads = "split"
+// long-strip = "slice"
+// gamma = 1.8
+// bits = 4
+// format = "png"
+//
+// Nothing set keeps the pages byte for byte as served.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Pipeline {
+    // Pages larger than this are shrunk to fit, keeping their aspect ratio.
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub resize_filter: Option<ResizeFilter>,
+    pub grayscale: Option<bool>,
+    // Cut away the uniform borders around the art.
+    pub crop_margins: Option<bool>,
+    // How far from the border color a pixel of the border may stray, out of 255.
+    pub crop_tolerance: Option<u8>,
+    // Percent of the width or height cut away at most.
+    pub crop_limit: Option<u8>,
+    pub spreads: Option<Spreads>,
+    pub reading_order: Option<ReadingOrder>,
+    // Also save a spread as it was, before the pages made of it.
+    pub keep_spreads: Option<bool>,
+    // Only for manhwa and manhua, done a chapter at a time rather than page by page.
+    pub long_strip: Option<LongStrip>,
+    // Target height of the slices of a long strip, the max height when not set.
+    pub strip_height: Option<u32>,
+    // Percent, negative values lower the contrast.
+    pub contrast: Option<f32>,
+    // Above 1 lightens the midtones, below 1 darkens them.
+    pub gamma: Option<f32>,
+    // Color depth per channel, e.g. 4 for the 16 grays of most e-ink screens.
+    pub bits: Option<u8>,
+    pub format: Option<PageFormat>,
+    // 1 to 100, for jpeg and avif.
+    pub quality: Option<u8>,
+}
+
+impl Pipeline {
+    // This pipeline, falling back to `other` for the settings not set.
+    #[must_use]
+    pub fn or(&self, other: &Pipeline) -> Pipeline {
+        Pipeline {
+            max_width: self.max_width.or(other.max_width),
+            max_height: self.max_height.or(other.max_height),
+            resize_filter: self.resize_filter.or(other.resize_filter),
+            grayscale: self.grayscale.or(other.grayscale),
+            crop_margins: self.crop_margins.or(other.crop_margins),
+            crop_tolerance: self.crop_tolerance.or(other.crop_tolerance),
+            crop_limit: self.crop_limit.or(other.crop_limit),
+            spreads: self.spreads.or(other.spreads),
+            reading_order: self.reading_order.or(other.reading_order),
+            keep_spreads: self.keep_spreads.or(other.keep_spreads),
+            long_strip: self.long_strip.or(other.long_strip),
+            strip_height: self.strip_height.or(other.strip_height),
+            contrast: self.contrast.or(other.contrast),
+            gamma: self.gamma.or(other.gamma),
+            bits: self.bits.or(other.bits),
+            format: self.format.or(other.format),
+            quality: self.quality.or(other.quality),
+        }
+    }
+
+    // Whether pages are saved untouched.
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        // The filter, quality, reading order, crop and strip settings alone change nothing to a single page.
+        self.max_width.is_none()
+            && self.max_height.is_none()
+            && self.grayscale != Some(true)
+            && self.crop_margins != Some(true)
+            && self.spreads.is_none_or(|spreads| spreads == Spreads::Keep)
+            && self.contrast.is_none()
+            && self.gamma.is_none()
+            && self.bits.is_none()
+            && self.format.is_none()
+    }
+
+    // Identifies the settings, so pages processed differently by a previous run are not reused.
+    #[must_use]
+    pub fn fingerprint(&self) -> String {
+        if self.is_identity() {
+            return String::new();
+        }
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    // Fail early on settings that can not work, rather than on every page.
+    pub fn check(&self) -> Result<()> {
+        if self.gamma.is_some_and(|gamma| gamma <= 0.0) {
+            return Err(Error::Processing("gamma has to be above 0".to_string()));
+        }
+        if self.bits.is_some_and(|bits| !(1..=8).contains(&bits)) {
+            return Err(Error::Processing("bits has to be from 1 to 8".to_string()));
+        }
+        if self.crop_limit.is_some_and(|limit| limit > 100) {
+            return Err(Error::Processing(
+                "crop limit has to be from 0 to 100".to_string(),
+            ));
+        }
+        if self
+            .quality
+            .is_some_and(|quality| !(1..=100).contains(&quality))
+        {
+            return Err(Error::Processing(
+                "quality has to be from 1 to 100".to_string(),
+            ));
+        }
+        if self.format == Some(PageFormat::Avif) && !cfg!(feature = "avif") {
+            return Err(Error::Processing(
+                "avif pages need mangadl-rs built with the avif feature".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // How the margins are cropped, `None` when they are kept.
+    #[must_use]
+    pub fn crop(&self) -> Option<Crop> {
+        (self.crop_margins == Some(true)).then(|| {
+            Crop::new(
+                self.crop_tolerance.unwrap_or(DEFAULT_TOLERANCE),
+                self.crop_limit.unwrap_or(DEFAULT_LIMIT),
+            )
+        })
+    }
+
+    // How the chapters of a long strip are stitched and cut again, `None` when the pages are kept as served.
+    #[must_use]
+    pub fn strip(&self) -> Option<Strip> {
+        match self.long_strip? {
+            LongStrip::Off => None,
+            LongStrip::Slice => Some(Strip::Sliced(self.strip_height.or(self.max_height))),
+            LongStrip::Single => Some(Strip::Whole),
+        }
+    }
+
+    // This pipeline for the cuts of a long strip, which are only cropped and sliced once stitched.
+    #[must_use]
+    pub fn for_strips(&self) -> Pipeline {
+        Pipeline {
+            max_height: None,
+            crop_margins: None,
+            spreads: None,
+            ..self.clone()
+        }
+    }
+
+    // This pipeline keeping the margins, for pages cropped later on.
+    #[must_use]
+    pub fn without_crop(&self) -> Pipeline {
+        Pipeline {
+            crop_margins: None,
+            ..self.clone()
+        }
+    }
+
+    // Process the raw bytes of a page into the pages saved for it, usually one.
+    // Returns the bytes as they are when there is nothing to do.
+    pub fn process(&self, bytes: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        if self.is_identity() {
+            return Ok(vec![bytes]);
+        }
+        let image = image::load_from_memory(&bytes)?;
+        let format = self.format.unwrap_or_else(|| PageFormat::guess(&bytes));
+        let quality = self.quality.unwrap_or(DEFAULT_QUALITY);
+        self.pages(image)
+            .into_iter()
+            .map(|page| encode(&self.apply(page), format, quality))
+            .collect()
+    }
+
+    // The pages saved for `image`, a spread giving its two pages in reading order or itself turned sideways.
+    #[must_use]
+    pub fn pages(&self, image: DynamicImage) -> Vec<DynamicImage> {
+        let spreads = self.spreads.unwrap_or(Spreads::Keep);
+        if spreads == Spreads::Keep || image.width() <= image.height() {
+            return vec![image];
+        }
+        let right_to_left = self.reading_order.unwrap_or_default() == ReadingOrder::RightToLeft;
+        let mut pages = Vec::new();
+        if self.keep_spreads == Some(true) {
+            pages.push(image.clone());
+        }
+        match spreads {
+            Spreads::Split => {
+                let (width, height) = (image.width(), image.height());
+                let left = image.crop_imm(0, 0, width / 2, height);
+                let right = image.crop_imm(width / 2, 0, width - width / 2, height);
+                if right_to_left {
+                    pages.extend([right, left]);
+                } else {
+                    pages.extend([left, right]);
+                }
+            }
+            // Turning it counterclockwise puts the right page on top.
+            Spreads::Rotate if right_to_left => pages.push(image.rotate270()),
+            Spreads::Rotate => pages.push(image.rotate90()),
+            Spreads::Keep => pages.push(image),
+        }
+        pages
+    }
+
+    // Every change but the format, in the order crop, resize, grayscale, contrast, gamma and depth.
+    #[must_use]
+    pub fn apply(&self, mut image: DynamicImage) -> DynamicImage {
+        if let Some(crop) = self.crop() {
+            image = crop.crop(image);
+        }
+        let (width, height) = (image.width(), image.height());
+        let max_width = self.max_width.unwrap_or(width);
+        let max_height = self.max_height.unwrap_or(height);
+        if width > max_width || height > max_height {
+            let filter = self.resize_filter.unwrap_or(ResizeFilter::Lanczos3);
+            image = image.resize(max_width, max_height, filter.into());
+        }
+        if self.grayscale == Some(true) {
+            image = DynamicImage::ImageLuma8(image.to_luma8());
+        }
+        if let Some(contrast) = self.contrast {
+            image = image.adjust_contrast(contrast);
+        }
+        if self.gamma.is_some() || self.bits.is_some() {
+            image = self.map_levels(image);
+        }
+        image
+    }
+
+    // Apply gamma and depth to every channel through a lookup table.
+    fn map_levels(&self, image: DynamicImage) -> DynamicImage {
+        let gamma = self.gamma.unwrap_or(1.0);
+        let levels = (1_u32 << self.bits.unwrap_or(8).clamp(1, 8)) - 1;
+        let mut table = [0_u8; 256];
+        for (value, mapped) in table.iter_mut().enumerate() {
+            let corrected = (value as f32 / 255.0).powf(1.0 / gamma);
+            let stepped = (corrected * levels as f32).round() / levels as f32;
+            *mapped = (stepped * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+
+        match image {
+            DynamicImage::ImageLuma8(mut gray) => {
+                for pixel in gray.pixels_mut() {
+                    pixel.0[0] = table[pixel.0[0] as usize];
+                }
+                DynamicImage::ImageLuma8(gray)
+            }
+            image => {
+                let mut rgb = image.to_rgb8();
+                for pixel in rgb.pixels_mut() {
+                    for channel in &mut pixel.0 {
+                        *channel = table[*channel as usize];
+                    }
+                }
+                DynamicImage::ImageRgb8(rgb)
+            }
+        }
+    }
+}
+
+// Encode a page in `format`, `quality` only mattering to jpeg and avif.
+pub fn encode(image: &DynamicImage, format: PageFormat, quality: u8) -> Result<Vec<u8>> {
+    // Pages have no use for transparency, and jpeg can not store it.
+    let image = match image.color() {
+        ColorType::L8 | ColorType::Rgb8 => image.clone(),
+        ColorType::La8 | ColorType::L16 | ColorType::La16 => {
+            DynamicImage::ImageLuma8(image.to_luma8())
+        }
+        _ => DynamicImage::ImageRgb8(image.to_rgb8()),
+    };
+    let mut bytes = Vec::new();
+    match format {
+        PageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut bytes))?,
+        PageFormat::Jpeg => {
+            image.write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?;
+        }
+        PageFormat::Webp => image.write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?,
+        #[cfg(feature = "avif")]
+        PageFormat::Avif => image.write_with_encoder(
+            image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut bytes, 6, quality),
+        )?,
+        #[cfg(not(feature = "avif"))]
+        PageFormat::Avif => {
+            return Err(Error::Processing(
+                "avif pages need mangadl-rs built with the avif feature".to_string(),
+            ))
+        }
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,122 @@
+use clap::ValueEnum;
+use image::{imageops, DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    pipeline::{encode, PageFormat, Pipeline, ResizeFilter, Spreads, DEFAULT_QUALITY},
+    types::SaveType,
+};
+
+// A device pages are made for, bundling the settings that suit its screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    KindlePaperwhite,
+    KoboClara,
+    #[value(name = "remarkable2")]
+    #[serde(rename = "remarkable2")]
+    Remarkable2,
+    #[value(name = "phone-1080p")]
+    #[serde(rename = "phone-1080p")]
+    Phone1080p,
+}
+
+impl Profile {
+    // Resolution of the screen, held upright.
+    #[must_use]
+    pub fn page_size(&self) -> PageSize {
+        match self {
+            Profile::KindlePaperwhite => PageSize::new(1236, 1648),
+            Profile::KoboClara => PageSize::new(1072, 1448),
+            Profile::Remarkable2 => PageSize::new(1404, 1872),
+            Profile::Phone1080p => PageSize::new(1080, 1920),
+        }
+    }
+
+    // Whether the screen is e-ink, which shows no color and wastes no space on margins.
+    #[must_use]
+    pub fn is_eink(&self) -> bool {
+        !matches!(self, Profile::Phone1080p)
+    }
+
+    // Image settings of the profile, below the ones configured or given on the command line.
+    #[must_use]
+    pub fn pipeline(&self) -> Pipeline {
+        let size = self.page_size();
+        Pipeline {
+            max_width: Some(size.width),
+            max_height: Some(size.height),
+            resize_filter: Some(ResizeFilter::Lanczos3),
+            grayscale: Some(self.is_eink()),
+            crop_margins: Some(self.is_eink()),
+            // A spread shrunk to the width of a small screen is unreadable.
+            spreads: Some(if self.is_eink() {
+                Spreads::Split
+            } else {
+                Spreads::Keep
+            }),
+            format: Some(match self {
+                Profile::Remarkable2 => PageFormat::Png,
+                _ => PageFormat::Jpeg,
+            }),
+            ..Pipeline::default()
+        }
+    }
+
+    // The container the device reads best.
+    #[must_use]
+    pub fn save_type(&self) -> SaveType {
+        match self {
+            Profile::KindlePaperwhite | Profile::KoboClara => SaveType::Epub,
+            Profile::Remarkable2 => SaveType::PdfSplit,
+            Profile::Phone1080p => SaveType::CbzSplit,
+        }
+    }
+}
+
+// Size in pixels every page of a pdf, epub or cbz is laid out on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PageSize {
+    #[must_use]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    // `image` scaled to fit the page and centered on white, so every page has the same size.
+    #[must_use]
+    pub fn fit(&self, image: DynamicImage) -> DynamicImage {
+        if (image.width(), image.height()) == (self.width, self.height) {
+            return image;
+        }
+        let image = image.resize(self.width, self.height, imageops::FilterType::Lanczos3);
+        let x = i64::from((self.width - image.width()) / 2);
+        let y = i64::from((self.height - image.height()) / 2);
+        match image {
+            DynamicImage::ImageLuma8(gray) => {
+                let mut page = GrayImage::from_pixel(self.width, self.height, Luma([255]));
+                imageops::overlay(&mut page, &gray, x, y);
+                DynamicImage::ImageLuma8(page)
+            }
+            image => {
+                let mut page = RgbImage::from_pixel(self.width, self.height, Rgb([255, 255, 255]));
+                imageops::overlay(&mut page, &image.to_rgb8(), x, y);
+                DynamicImage::ImageRgb8(page)
+            }
+        }
+    }
+
+    // Raw bytes of a page fitted to the page, in the format they came in.
+    pub fn fit_bytes(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        let image = image::load_from_memory(&bytes)?;
+        if (image.width(), image.height()) == (self.width, self.height) {
+            return Ok(bytes);
+        }
+        encode(&self.fit(image), PageFormat::guess(&bytes), DEFAULT_QUALITY)
+    }
+}
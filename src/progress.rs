@@ -0,0 +1,374 @@
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display, Formatter},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::{chapter::Page, error::Error, range::ChapterNumber};
+
+// Stage of a download, each one reporting its own steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    // Getting the page urls of every chapter, a step per chapter.
+    Urls,
+    // Downloading the pages, reported page by page.
+    Download,
+    // Decoding the pages into memory, a step per page.
+    Decode,
+    // Writing the output files, reported file by file.
+    Write,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Urls => write!(f, "Fetching urls"),
+            Phase::Download => write!(f, "Downloading images"),
+            Phase::Decode => write!(f, "Loading images to ram"),
+            Phase::Write => write!(f, "Saving"),
+        }
+    }
+}
+
+// Something that happened during a download. Chapters are url ids, e.g. "0010.5" for chapter 10.5.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    // A new phase began, made of `total` chapters, pages or files.
+    Phase {
+        phase: Phase,
+        total: usize,
+    },
+    // One more chapter or page of the urls or decode phase is done.
+    Step {
+        item: String,
+    },
+    // The first page of a chapter was picked up.
+    ChapterStarted {
+        chapter: String,
+        pages: usize,
+    },
+    // A page was saved, `resumed` when a previous run already had it.
+    PageDone {
+        chapter: String,
+        page: usize,
+        bytes: u64,
+        resumed: bool,
+    },
+    PageFailed {
+        chapter: String,
+        page: usize,
+        error: String,
+    },
+    // Every page of a chapter was saved or failed.
+    ChapterDone {
+        chapter: String,
+    },
+    FileWritten {
+        path: PathBuf,
+    },
+    Finished {
+        failed: usize,
+    },
+    // A network call failed and is tried again after `delay_ms`, `attempt` out of `attempts` having failed.
+    Retry {
+        error: String,
+        attempt: u32,
+        attempts: u32,
+        delay_ms: u64,
+    },
+    // Something went wrong that the download gets by without, e.g. a catalogue that could not be cached.
+    Warning {
+        message: String,
+    },
+}
+
+// Receives the events of downloads, to show them however suits.
+// Called from many tasks at once, so implementations have to handle their own locking.
+pub trait ProgressSink: Send + Sync {
+    fn event(&self, event: Event);
+
+    // Whether stdout is taken by events for other programs, so text for people has to go to stderr.
+    fn owns_stdout(&self) -> bool {
+        false
+    }
+}
+
+impl Debug for dyn ProgressSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ProgressSink")
+    }
+}
+
+// Ignores every event.
+pub struct Silent;
+
+impl ProgressSink for Silent {
+    fn event(&self, _event: Event) {}
+}
+
+// How the command line shows progress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressFormat {
+    // Progress bars, overall and per chapter.
+    #[default]
+    Bars,
+    // A line per chapter, for logs.
+    Plain,
+    // An event per line as json, for other programs.
+    Json,
+}
+
+#[must_use]
+pub fn renderer(format: ProgressFormat, unicode: bool) -> Arc<dyn ProgressSink> {
+    match format {
+        ProgressFormat::Bars => Arc::new(Bars::new(unicode)),
+        ProgressFormat::Plain => Arc::new(PlainLog),
+        ProgressFormat::Json => Arc::new(JsonLines),
+    }
+}
+
+// An overall bar for the current phase, and a bar for every chapter being downloaded.
+pub struct Bars {
+    multi: MultiProgress,
+    style: ProgressStyle,
+    state: Mutex<BarsState>,
+}
+
+#[derive(Default)]
+struct BarsState {
+    phase: Option<Phase>,
+    overall: Option<ProgressBar>,
+    chapters: HashMap<String, ProgressBar>,
+}
+
+impl Bars {
+    #[must_use]
+    pub fn new(unicode: bool) -> Self {
+        let style = if unicode {
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+            )
+            .unwrap()
+        } else {
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+            )
+            .expect("Failed to create progress style")
+            .progress_chars("#>-")
+        };
+        Self {
+            multi: MultiProgress::new(),
+            style,
+            state: Mutex::new(BarsState::default()),
+        }
+    }
+
+    fn bar(&self, len: usize, message: String) -> ProgressBar {
+        self.multi.add(
+            ProgressBar::new(len as u64)
+                .with_style(self.style.clone())
+                .with_message(message),
+        )
+    }
+}
+
+impl ProgressSink for Bars {
+    fn event(&self, event: Event) {
+        let mut state = self.state.lock().unwrap();
+        let inc = |bar: Option<&ProgressBar>| {
+            if let Some(bar) = bar {
+                bar.inc(1);
+            }
+        };
+        match event {
+            Event::Phase { phase, total } => {
+                if let Some(overall) = state.overall.take() {
+                    overall.finish();
+                }
+                state.phase = Some(phase);
+                state.overall = Some(self.bar(total, phase.to_string()));
+            }
+            Event::Step { .. } => inc(state.overall.as_ref()),
+            Event::ChapterStarted { chapter, pages } => {
+                let bar = self.bar(pages, chapter_name(&chapter));
+                state.chapters.insert(chapter, bar);
+            }
+            Event::PageDone { chapter, .. } | Event::PageFailed { chapter, .. } => {
+                inc(state.overall.as_ref());
+                inc(state.chapters.get(&chapter));
+            }
+            Event::ChapterDone { chapter } => {
+                if let Some(bar) = state.chapters.remove(&chapter) {
+                    bar.finish_and_clear();
+                    self.multi.remove(&bar);
+                }
+            }
+            Event::FileWritten { path } => {
+                if state.phase == Some(Phase::Write) {
+                    inc(state.overall.as_ref());
+                } else {
+                    // Written while still downloading, e.g. the pdf of a finished chapter.
+                    let _ = self.multi.println(format!("Saved {}", path.display()));
+                }
+            }
+            Event::Finished { .. } => {
+                if let Some(overall) = state.overall.take() {
+                    overall.finish();
+                }
+                state.phase = None;
+            }
+            // Printed above the bars, which would be torn apart by a line written past them.
+            Event::Retry { .. } | Event::Warning { .. } => {
+                if let Some(line) = notice(&event) {
+                    let _ = self.multi.println(line);
+                }
+            }
+        }
+    }
+}
+
+// Human readable lines, without redrawing anything.
+pub struct PlainLog;
+
+impl ProgressSink for PlainLog {
+    fn event(&self, event: Event) {
+        match event {
+            Event::Phase { phase, total } => println!("{phase} ({total})..."),
+            Event::ChapterStarted { chapter, pages } => {
+                println!("{}: {pages} pages", chapter_name(&chapter));
+            }
+            Event::PageFailed {
+                chapter,
+                page,
+                error,
+            } => eprintln!("{} page {page} failed: {error}", chapter_name(&chapter)),
+            Event::ChapterDone { chapter } => println!("{} done", chapter_name(&chapter)),
+            Event::FileWritten { path } => println!("Saved {}", path.display()),
+            Event::Finished { failed } => println!("Finished, {failed} pages failed"),
+            Event::Retry { .. } | Event::Warning { .. } => {
+                if let Some(line) = notice(&event) {
+                    eprintln!("{line}");
+                }
+            }
+            Event::Step { .. } | Event::PageDone { .. } => {}
+        }
+    }
+}
+
+// Every event as a line of json on stdout.
+pub struct JsonLines;
+
+impl ProgressSink for JsonLines {
+    fn event(&self, event: Event) {
+        if let Ok(line) = serde_json::to_string(&event) {
+            println!("{line}");
+        }
+    }
+
+    fn owns_stdout(&self) -> bool {
+        true
+    }
+}
+
+// Line shown for retries and warnings.
+fn notice(event: &Event) -> Option<String> {
+    match event {
+        Event::Retry {
+            error,
+            attempt,
+            attempts,
+            delay_ms,
+        } => Some(format!(
+            "Error: {error}, Retrying in {:.1}s ({attempt}/{attempts})!",
+            *delay_ms as f32 / 1000.0
+        )),
+        Event::Warning { message } => Some(format!("Error: {message}!")),
+        _ => None,
+    }
+}
+
+// Turns the pages of a download into chapter events, knowing how many pages every chapter has.
+pub struct ChapterTracker {
+    sink: Arc<dyn ProgressSink>,
+    chapters: Mutex<HashMap<String, ChapterCount>>,
+}
+
+impl ChapterTracker {
+    #[must_use]
+    pub fn new(sink: Arc<dyn ProgressSink>, pages: &[Page]) -> Self {
+        let mut chapters = HashMap::<String, ChapterCount>::new();
+        for page in pages {
+            let count = chapters.entry(page.chapter.clone()).or_default();
+            count.pages += 1;
+            count.left += 1;
+        }
+        Self {
+            sink,
+            chapters: Mutex::new(chapters),
+        }
+    }
+
+    // A worker picked up `page`.
+    pub fn started(&self, page: &Page) {
+        let mut chapters = self.chapters.lock().unwrap();
+        if let Some(count) = chapters.get_mut(&page.chapter) {
+            if !count.started {
+                count.started = true;
+                self.sink.event(Event::ChapterStarted {
+                    chapter: page.chapter.clone(),
+                    pages: count.pages,
+                });
+            }
+        }
+    }
+
+    pub fn done(&self, page: &Page, bytes: u64, resumed: bool) {
+        self.sink.event(Event::PageDone {
+            chapter: page.chapter.clone(),
+            page: page.number,
+            bytes,
+            resumed,
+        });
+        self.finish_page(page);
+    }
+
+    pub fn failed(&self, page: &Page, error: &Error) {
+        self.sink.event(Event::PageFailed {
+            chapter: page.chapter.clone(),
+            page: page.number,
+            error: error.to_string(),
+        });
+        self.finish_page(page);
+    }
+
+    fn finish_page(&self, page: &Page) {
+        let mut chapters = self.chapters.lock().unwrap();
+        if let Some(count) = chapters.get_mut(&page.chapter) {
+            count.left -= 1;
+            if count.left == 0 {
+                self.sink.event(Event::ChapterDone {
+                    chapter: page.chapter.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct ChapterCount {
+    pages: usize,
+    // Pages not done yet.
+    left: usize,
+    started: bool,
+}
+
+// "0010.5" is shown as "Chapter 10.5".
+pub(crate) fn chapter_name(chapter: &str) -> String {
+    format!("Chapter {}", ChapterNumber::display_id(chapter))
+}
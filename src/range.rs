@@ -0,0 +1,324 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crate::{
+    chapter::Chapter,
+    error::{Error, Result},
+};
+
+// A chapter number in tenths, the way mangasee encodes it. "234.5" is 2345.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ChapterNumber(pub u32);
+
+impl ChapterNumber {
+    // The url id `id` as people read it, "0010.5" being "10.5". Ids that are no chapter number are kept as they are.
+    #[must_use]
+    pub fn display_id(id: &str) -> String {
+        id.parse::<ChapterNumber>()
+            .map_or_else(|_| id.to_string(), |number| number.to_string())
+    }
+}
+
+impl FromStr for ChapterNumber {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Range(s.to_string());
+        let (whole, odd) = s.trim().split_once('.').unwrap_or((s.trim(), "0"));
+        // Mangasee only has a single digit for the odd part of a chapter.
+        if odd.len() != 1 {
+            return Err(invalid());
+        }
+        let whole = whole.parse::<u32>().map_err(|_| invalid())?;
+        let odd = odd.parse::<u32>().map_err(|_| invalid())?;
+        whole
+            .checked_mul(10)
+            .and_then(|whole| whole.checked_add(odd))
+            .map(Self)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Display for ChapterNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.0 / 10, self.0 % 10) {
+            (whole, 0) => write!(f, "{whole}"),
+            (whole, odd) => write!(f, "{whole}.{odd}"),
+        }
+    }
+}
+
+// One comma separated part of a chapter selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChapterRange {
+    // "all"
+    All,
+    // "72" or "100.5"
+    Single(ChapterNumber),
+    // "1-50"
+    Between(ChapterNumber, ChapterNumber),
+    // "100.5-"
+    From(ChapterNumber),
+    // "-20"
+    UpTo(ChapterNumber),
+    // "latest:5", the 5 highest chapters
+    Latest(usize),
+}
+
+impl ChapterRange {
+    // Whether the chapter `number` is selected, `sorted` being every chapter number of the manga in order.
+    #[must_use]
+    pub fn contains(&self, number: ChapterNumber, sorted: &[ChapterNumber]) -> bool {
+        match *self {
+            ChapterRange::All => true,
+            ChapterRange::Single(single) => number == single,
+            ChapterRange::Between(first, last) => (first..=last).contains(&number),
+            ChapterRange::From(first) => number >= first,
+            ChapterRange::UpTo(last) => number <= last,
+            ChapterRange::Latest(count) => {
+                sorted.len() <= count || number >= sorted[sorted.len() - count]
+            }
+        }
+    }
+}
+
+impl FromStr for ChapterRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(ChapterRange::All);
+        }
+        if let Some(count) = s.strip_prefix("latest:") {
+            return match count.trim().parse::<usize>() {
+                Ok(count) if count > 0 => Ok(ChapterRange::Latest(count)),
+                _ => Err(Error::Range(s.to_string())),
+            };
+        }
+        match s.split_once('-') {
+            None => Ok(ChapterRange::Single(s.parse()?)),
+            Some((first, last)) if last.trim().is_empty() => Ok(ChapterRange::From(first.parse()?)),
+            Some((first, last)) if first.trim().is_empty() => Ok(ChapterRange::UpTo(last.parse()?)),
+            Some((first, last)) => {
+                let (first, last) = (first.parse()?, last.parse()?);
+                if first > last {
+                    return Err(Error::Range(s.to_string()));
+                }
+                Ok(ChapterRange::Between(first, last))
+            }
+        }
+    }
+}
+
+impl Display for ChapterRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChapterRange::All => write!(f, "all"),
+            ChapterRange::Single(single) => write!(f, "{single}"),
+            ChapterRange::Between(first, last) => write!(f, "{first}-{last}"),
+            ChapterRange::From(first) => write!(f, "{first}-"),
+            ChapterRange::UpTo(last) => write!(f, "-{last}"),
+            ChapterRange::Latest(count) => write!(f, "latest:{count}"),
+        }
+    }
+}
+
+// Chapters picked with comma separated ranges, e.g. "1-50,72,100.5-,latest:5" or "all".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterSelection(pub Vec<ChapterRange>);
+
+impl ChapterSelection {
+    // The selected chapters, in the order of `chapters`.
+    #[must_use]
+    pub fn select(&self, chapters: &[Chapter]) -> Vec<Chapter> {
+        self.select_by(chapters, Chapter::number)
+    }
+
+    // The selected items, in the order of `items`, `number` being the chapter number of an item.
+    #[must_use]
+    pub fn select_by<T: Clone>(&self, items: &[T], number: impl Fn(&T) -> ChapterNumber) -> Vec<T> {
+        let mut sorted = items.iter().map(&number).collect::<Vec<_>>();
+        sorted.sort_unstable();
+        items
+            .iter()
+            .filter(|item| {
+                self.0
+                    .iter()
+                    .any(|range| range.contains(number(item), &sorted))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for ChapterSelection {
+    fn default() -> Self {
+        Self(vec![ChapterRange::All])
+    }
+}
+
+impl FromStr for ChapterSelection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let ranges = s
+            .split(',')
+            .filter(|part| !part.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if ranges.is_empty() {
+            return Err(Error::Range(s.to_string()));
+        }
+        Ok(Self(ranges))
+    }
+}
+
+impl Display for ChapterSelection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ranges = self.0.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(s: &str) -> ChapterNumber {
+        s.parse().unwrap()
+    }
+
+    fn range(s: &str) -> ChapterRange {
+        s.parse().unwrap()
+    }
+
+    fn chapter(encoded: &str) -> Chapter {
+        Chapter {
+            Chapter: encoded.to_string(),
+            Page: String::new(),
+            Directory: String::new(),
+        }
+    }
+
+    // The chapters of `selection` among 1 to 10 and 10.5, as people read them.
+    fn selected(selection: &str) -> Vec<String> {
+        let chapters = (1..=10)
+            .map(|n| format!("1{n:04}0"))
+            .chain(["100105".to_string()])
+            .map(|encoded| chapter(&encoded))
+            .collect::<Vec<_>>();
+        selection
+            .parse::<ChapterSelection>()
+            .unwrap()
+            .select(&chapters)
+            .iter()
+            .map(|chapter| chapter.number().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn chapter_numbers() {
+        assert_eq!(number("72"), ChapterNumber(720));
+        assert_eq!(number("10.5"), ChapterNumber(105));
+        assert_eq!(number(" 0010.5 "), ChapterNumber(105));
+        assert_eq!(number("10.5").to_string(), "10.5");
+        assert_eq!(number("10.0").to_string(), "10");
+        for invalid in ["", "ten", "10.", "10.55", ".5", "-1", "4294967295"] {
+            assert!(invalid.parse::<ChapterNumber>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn display_ids() {
+        assert_eq!(ChapterNumber::display_id("0010.5"), "10.5");
+        assert_eq!(ChapterNumber::display_id("0072"), "72");
+        assert_eq!(ChapterNumber::display_id("special"), "special");
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(range("all"), ChapterRange::All);
+        assert_eq!(range("ALL"), ChapterRange::All);
+        assert_eq!(range("72"), ChapterRange::Single(number("72")));
+        assert_eq!(
+            range("1-50"),
+            ChapterRange::Between(number("1"), number("50"))
+        );
+        assert_eq!(
+            range("5-5"),
+            ChapterRange::Between(number("5"), number("5"))
+        );
+        assert_eq!(range("50-"), ChapterRange::From(number("50")));
+        assert_eq!(range("100.5-"), ChapterRange::From(number("100.5")));
+        assert_eq!(range("-20"), ChapterRange::UpTo(number("20")));
+        assert_eq!(range("latest:5"), ChapterRange::Latest(5));
+        assert_eq!(range(" latest: 5 "), ChapterRange::Latest(5));
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        for invalid in [
+            "",
+            "-",
+            "50-1",
+            "10.5-10",
+            "1-2-3",
+            "a-b",
+            "latest:",
+            "latest:0",
+            "latest:-1",
+            "latest:x",
+            "newest:5",
+        ] {
+            assert!(invalid.parse::<ChapterRange>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn ranges_display_as_parsed() {
+        for s in ["all", "72", "10.5", "1-50", "50-", "-20", "latest:5"] {
+            assert_eq!(range(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn selections() {
+        let selection = "1-50, 72,,100.5-,latest:5"
+            .parse::<ChapterSelection>()
+            .unwrap();
+        assert_eq!(
+            selection.0,
+            vec![
+                range("1-50"),
+                range("72"),
+                range("100.5-"),
+                range("latest:5")
+            ]
+        );
+        assert_eq!(selection.to_string(), "1-50,72,100.5-,latest:5");
+        assert_eq!(ChapterSelection::default().to_string(), "all");
+        for invalid in ["", ",", " , ", "1,x", "1,50-1"] {
+            assert!(invalid.parse::<ChapterSelection>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn selecting_chapters() {
+        assert_eq!(selected("all").len(), 11);
+        assert_eq!(selected("10.5"), ["10.5"]);
+        assert_eq!(selected("-3"), ["1", "2", "3"]);
+        assert_eq!(selected("9-"), ["9", "10", "10.5"]);
+        assert_eq!(selected("2-4"), ["2", "3", "4"]);
+        assert_eq!(selected("latest:2"), ["10", "10.5"]);
+        assert_eq!(selected("latest:100").len(), 11);
+        assert!(selected("11").is_empty());
+    }
+
+    #[test]
+    fn selecting_keeps_order_and_drops_duplicates() {
+        assert_eq!(selected("3,1-2,2,latest:1,10.5"), ["1", "2", "3", "10.5"]);
+    }
+}
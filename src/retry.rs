@@ -0,0 +1,138 @@
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+use std::{future::Future, time::Duration};
+use tokio::time::sleep;
+
+use crate::{
+    error::{Error, Result},
+    progress::{Event, ProgressSink},
+};
+
+// How often, and how patiently, failed network calls are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Longest wait a server may ask for with Retry-After, the call fails when it asks for more.
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(30),
+            max_retry_after: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            base_delay,
+            max_delay,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    // Exponential delay before the next try, after `attempt` failed ones.
+    // Jitter keeps the threads from hammering the site in lockstep.
+    #[must_use]
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        let millis = exponential.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(millis / 2..=millis))
+    }
+
+    // Run `operation` until it succeeds, fails with an error not worth retrying or runs out of attempts.
+    // Every retry is reported to `progress`.
+    pub async fn run<T, F, Fut>(&self, progress: &dyn ProgressSink, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(e) if attempt < self.max_attempts && is_retryable(&e) => {
+                    let delay = match &e {
+                        // The wait a server asks for is honored in full, retrying sooner would only be refused again.
+                        // One longer than allowed fails the call instead of stalling the download.
+                        Error::Status {
+                            url,
+                            retry_after: Some(retry_after),
+                            ..
+                        } => {
+                            if *retry_after > self.max_retry_after {
+                                break Err(Error::RetryAfter {
+                                    url: url.clone(),
+                                    retry_after: *retry_after,
+                                    max: self.max_retry_after,
+                                });
+                            }
+                            *retry_after
+                        }
+                        _ => self.backoff(attempt),
+                    };
+                    progress.event(Event::Retry {
+                        error: e.to_string(),
+                        attempt,
+                        attempts: self.max_attempts,
+                        delay_ms: delay.as_millis() as u64,
+                    });
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        }
+    }
+}
+
+// Send a request once, turning non success status codes into errors.
+pub async fn send(request: RequestBuilder) -> Result<Response> {
+    let response = request.send().await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = match status {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs),
+        _ => None,
+    };
+    Err(Error::Status {
+        url: response.url().to_string(),
+        status,
+        retry_after,
+    })
+}
+
+// Connection problems, rate limiting and server errors may go away, anything else won't.
+fn is_retryable(error: &Error) -> bool {
+    match error {
+        Error::Network(_) => true,
+        Error::Status { status, .. } => {
+            *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use reqwest::Url;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Runs tasks from a single queue on at most `workers` tokio tasks at a time.
+// A worker takes the next task as soon as it is free, so one slow page never leaves the others idle.
+// Clones share the same workers.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    permits: Arc<Semaphore>,
+}
+
+impl Scheduler {
+    #[must_use]
+    pub fn new(workers: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(workers.max(1))),
+        }
+    }
+
+    // Run `task` on every item, returning the results in the order of the items.
+    pub async fn run<T, R, F, Fut>(&self, items: impl IntoIterator<Item = T>, task: F) -> Vec<R>
+    where
+        F: Fn(T) -> Fut,
+        Fut: Future<Output = R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let mut handles = Vec::new();
+        for item in items {
+            let permit = self
+                .permits
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("Scheduler semaphore closed");
+            let future = task(item);
+            handles.push(tokio::spawn(async move {
+                let result = future.await;
+                drop(permit);
+                result
+            }));
+        }
+
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(handle.await.expect("Download task panicked"));
+        }
+        results
+    }
+}
+
+// Caps the requests in flight to any single host, however many workers there are.
+// Image servers throttle or ban clients opening too many connections at once.
+#[derive(Debug)]
+pub struct HostLimits {
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+// Requests to one host at a time, when not configured.
+pub const DEFAULT_PER_HOST: usize = 4;
+
+impl Default for HostLimits {
+    fn default() -> Self {
+        Self::new(DEFAULT_PER_HOST)
+    }
+}
+
+impl HostLimits {
+    #[must_use]
+    pub fn new(per_host: usize) -> Self {
+        Self {
+            per_host: per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Wait for a free slot on the host of `url`, held until the permit is dropped.
+    pub async fn acquire(&self, url: &str) -> OwnedSemaphorePermit {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let permits = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+        permits
+            .acquire_owned()
+            .await
+            .expect("Host semaphore closed")
+    }
+}
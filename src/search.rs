@@ -0,0 +1,196 @@
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// Text searched for, folded once so it can be matched against the whole catalogue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    text: String,
+    words: Vec<String>,
+}
+
+impl Query {
+    #[must_use]
+    pub fn new(text: &str) -> Self {
+        let text = fold(text);
+        let words = text.split(' ').map(str::to_string).collect();
+        Self { text, words }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    // How well `candidate` matches, higher being better, `None` when it does not match at all.
+    #[must_use]
+    pub fn score(&self, candidate: &str) -> Option<u32> {
+        if self.is_empty() {
+            return Some(0);
+        }
+        let candidate = fold(candidate);
+        if candidate == self.text {
+            return Some(1000);
+        }
+        if candidate.starts_with(&self.text) {
+            return Some(900);
+        }
+        if let Some(position) = candidate.find(&self.text) {
+            let word_start = candidate.as_bytes()[position - 1] == b' ';
+            return Some(if word_start { 800 } else { 700 });
+        }
+        if let Some(typos) = self.word_typos(&candidate) {
+            return Some(600 - 50 * typos.min(10));
+        }
+        subsequence(&self.text, &candidate).map(|tightness| 100 + tightness)
+    }
+
+    // Typos needed for every word of the query to start a word of `candidate`,
+    // so "one pice" still finds "One Piece".
+    fn word_typos(&self, candidate: &str) -> Option<u32> {
+        let words = candidate.split(' ').collect::<Vec<_>>();
+        self.words.iter().try_fold(0, |typos, query_word| {
+            words
+                .iter()
+                .filter_map(|word| word_typos(query_word, word))
+                .min()
+                .map(|best| typos + best)
+        })
+    }
+}
+
+// Lowercase `text` and strip its accents and punctuation, "Pokémon: Adventures" becomes "pokemon adventures".
+#[must_use]
+pub fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Typos between `query` and the start of `word`, within what its length allows.
+fn word_typos(query: &str, word: &str) -> Option<u32> {
+    if word.starts_with(query) {
+        return Some(0);
+    }
+    let allowed = match query.chars().count() {
+        0..=3 => return None,
+        4..=7 => 1,
+        _ => 2,
+    };
+    // Compare against the word cut to the query length, give or take the allowed typos.
+    let word = word.chars().collect::<Vec<_>>();
+    let length = query.chars().count();
+    (length.saturating_sub(allowed)..=length + allowed)
+        .filter(|end| *end <= word.len() && *end > 0)
+        .map(|end| distance(query, &word[..end]))
+        .min()
+        .filter(|typos| *typos <= allowed as u32)
+}
+
+// Edit distance, swapping two neighbouring letters counting as a single typo.
+fn distance(a: &str, b: &[char]) -> u32 {
+    let a = a.chars().collect::<Vec<_>>();
+    let mut rows = vec![(0..=b.len() as u32).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i as u32];
+        for j in 1..=b.len() {
+            let mut best = (rows[i - 1][j - 1] + u32::from(a[i - 1] != b[j - 1]))
+                .min(rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            row.push(best);
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+// Whether the letters of `query` appear in order in `candidate`, so "opm" finds "One Punch-Man".
+// Returns up to 200 the closer together they are.
+fn subsequence(query: &str, candidate: &str) -> Option<u32> {
+    let mut letters = query.chars().filter(|c| *c != ' ').peekable();
+    let length = query.chars().filter(|c| *c != ' ').count();
+    let (mut first, mut last) = (None, 0);
+    for (i, c) in candidate.chars().enumerate() {
+        if letters.peek() == Some(&c) {
+            letters.next();
+            first.get_or_insert(i);
+            last = i;
+        }
+    }
+    if letters.peek().is_some() {
+        return None;
+    }
+    let span = last - first.unwrap_or_default() + 1;
+    Some((200 * length / span) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, candidate: &str) -> Option<u32> {
+        Query::new(query).score(candidate)
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold("Pokémon: Adventures"), "pokemon adventures");
+        assert_eq!(fold("  One   Punch-Man!! "), "one punch man");
+        assert_eq!(fold("ÀÉÎ"), "aei");
+        assert_eq!(fold("?!"), "");
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(score("", "One Piece"), Some(0));
+        assert_eq!(score(" - ", "One Piece"), Some(0));
+    }
+
+    #[test]
+    fn exact_prefix_and_inner_matches() {
+        assert_eq!(score("one piece", "One Piece"), Some(1000));
+        assert_eq!(score("pokemon", "Pokémon"), Some(1000));
+        assert_eq!(score("one", "One Piece"), Some(900));
+        assert_eq!(score("piece", "One Piece"), Some(800));
+        assert_eq!(score("iece", "One Piece"), Some(700));
+    }
+
+    #[test]
+    fn typos() {
+        assert_eq!(score("one pice", "One Piece"), Some(550));
+        assert_eq!(score("berserq", "Berserk"), Some(550));
+        assert_eq!(score("peice", "One Piece"), Some(550));
+        // Short words leave no room for typos.
+        assert_eq!(score("oen", "One Piece"), None);
+        // Nor do words with more typos than their length allows.
+        assert_eq!(score("bxrsxrk", "Berserk"), None);
+    }
+
+    #[test]
+    fn abbreviations() {
+        assert_eq!(score("opm", "One Punch-Man"), Some(100 + 200 * 3 / 11));
+        assert_eq!(score("op", "One Piece").map(|s| s > 100), Some(true));
+        assert_eq!(score("xyz", "One Piece"), None);
+    }
+
+    #[test]
+    fn better_matches_score_higher() {
+        let query = Query::new("kaguya");
+        let exact = query.score("Kaguya");
+        let prefix = query.score("Kaguya-sama: Love Is War");
+        let inner = query.score("The Tale of Princess Kaguya");
+        let typo = Query::new("kaguja").score("Kaguya-sama");
+        let abbreviation = Query::new("ksl").score("Kaguya-sama: Love Is War");
+        assert!(exact > prefix);
+        assert!(prefix > inner);
+        assert!(inner > typo);
+        assert!(typo > abbreviation);
+        assert!(abbreviation > None);
+    }
+}
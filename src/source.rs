@@ -0,0 +1,39 @@
+use async_trait::async_trait;
+
+use crate::{
+    chapter::{Chapter, ChapterInfo},
+    error::Result,
+    manga::Manga,
+};
+
+mod cached;
+#[cfg(test)]
+mod fake;
+mod mangasee;
+
+pub use cached::{CacheMode, CachedSource, DEFAULT_TTL};
+#[cfg(test)]
+pub use fake::FakeSource;
+pub use mangasee::MangaseeSource;
+
+// A website manga can be downloaded from.
+// Everything in fetch only talks to a source, so a different site (or an in-memory fake) can be plugged in.
+#[async_trait]
+pub trait Source: Send + Sync {
+    // Every manga the source offers.
+    async fn catalogue(&self) -> Result<Vec<Manga>>;
+
+    // Every chapter of a manga, oldest first.
+    async fn chapters(&self, manga: &Manga) -> Result<Vec<Chapter>>;
+
+    // Release dates of the chapters of a manga, for sources that know them.
+    async fn chapter_infos(&self, _manga: &Manga) -> Result<Vec<ChapterInfo>> {
+        Ok(Vec::new())
+    }
+
+    // Image urls of every page of a chapter, in reading order.
+    async fn page_urls(&self, manga: &Manga, chapter: &Chapter) -> Result<Vec<String>>;
+
+    // Raw bytes of a page image.
+    async fn fetch_image(&self, url: &str) -> Result<Vec<u8>>;
+}
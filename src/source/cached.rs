@@ -0,0 +1,179 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::Source;
+use crate::{
+    chapter::{Chapter, ChapterInfo},
+    error::{Error, Result},
+    manga::Manga,
+    progress::{Event, ProgressSink, Silent},
+    store::{read_if_exists, write_atomic},
+};
+
+const FILE_NAME: &str = "catalogue.json";
+
+// How long a cached catalogue is used before it is downloaded again.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    // Use the cache while it is fresh, and when the site cannot be reached.
+    #[default]
+    Normal,
+    // Always download the catalogue, e.g. to see the latest chapters.
+    Refresh,
+    // Never download the catalogue, only browse the cached one.
+    Offline,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedCatalogue {
+    // Seconds since the unix epoch.
+    fetched_at: u64,
+    manga: Vec<Manga>,
+}
+
+// Keeps the catalogue of `source` on disk, as downloading and decoding it takes a while.
+// Everything else goes to the source as is.
+pub struct CachedSource<S> {
+    source: S,
+    file: PathBuf,
+    ttl: Duration,
+    mode: CacheMode,
+    // Where the problems with the cache are reported.
+    progress: Arc<dyn ProgressSink>,
+}
+
+impl<S: Source> CachedSource<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            file: Self::default_path(),
+            ttl: DEFAULT_TTL,
+            mode: CacheMode::default(),
+            progress: Arc::new(Silent),
+        }
+    }
+
+    // Where the catalogue is kept, e.g. "~/.cache/mangadl-rs/catalogue.json" on linux.
+    #[must_use]
+    pub fn default_path() -> PathBuf {
+        dirs::cache_dir()
+            .unwrap_or_default()
+            .join("mangadl-rs")
+            .join(FILE_NAME)
+    }
+
+    #[must_use]
+    pub fn with_file(mut self, file: PathBuf) -> Self {
+        self.file = file;
+        self
+    }
+
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    #[must_use]
+    pub fn with_mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    #[must_use]
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    fn warn(&self, message: String) {
+        self.progress.event(Event::Warning { message });
+    }
+
+    // Download the catalogue and cache it, a failed write only costing the next launch some time.
+    async fn fetch(&self) -> Result<Vec<Manga>> {
+        let manga = self.source.catalogue().await?;
+        let cached = CachedCatalogue {
+            fetched_at: now(),
+            manga,
+        };
+        if let Err(e) = save(&self.file, &cached) {
+            self.warn(format!("{e}, the catalogue will not be cached"));
+        }
+        Ok(cached.manga)
+    }
+}
+
+#[async_trait]
+impl<S: Source> Source for CachedSource<S> {
+    async fn catalogue(&self) -> Result<Vec<Manga>> {
+        match self.mode {
+            CacheMode::Refresh => self.fetch().await,
+            CacheMode::Offline => load(&self.file)?
+                .map(|cached| cached.manga)
+                .ok_or(Error::NoCatalogue),
+            CacheMode::Normal => {
+                let cached = load(&self.file).unwrap_or_else(|e| {
+                    self.warn(format!("{e}, downloading the catalogue again"));
+                    None
+                });
+                let Some(cached) = cached else {
+                    return self.fetch().await;
+                };
+                if now().saturating_sub(cached.fetched_at) < self.ttl.as_secs() {
+                    return Ok(cached.manga);
+                }
+                match self.fetch().await {
+                    Err(e @ (Error::Network(_) | Error::Status { .. })) => {
+                        self.warn(format!("{e}, using the catalogue cached before"));
+                        Ok(cached.manga)
+                    }
+                    fetched => fetched,
+                }
+            }
+        }
+    }
+
+    async fn chapters(&self, manga: &Manga) -> Result<Vec<Chapter>> {
+        self.source.chapters(manga).await
+    }
+
+    async fn chapter_infos(&self, manga: &Manga) -> Result<Vec<ChapterInfo>> {
+        self.source.chapter_infos(manga).await
+    }
+
+    async fn page_urls(&self, manga: &Manga, chapter: &Chapter) -> Result<Vec<String>> {
+        self.source.page_urls(manga, chapter).await
+    }
+
+    async fn fetch_image(&self, url: &str) -> Result<Vec<u8>> {
+        self.source.fetch_image(url).await
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// The cached catalogue, `None` when there is none yet.
+fn load(file: &Path) -> Result<Option<CachedCatalogue>> {
+    match read_if_exists(file, fs::read)? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+fn save(file: &Path, cached: &CachedCatalogue) -> Result<()> {
+    write_atomic(file, &serde_json::to_vec(cached)?)
+}
@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+use std::{collections::HashMap, sync::Mutex};
+
+use super::Source;
+use crate::{
+    chapter::Chapter,
+    error::{Error, Result},
+    manga::Manga,
+};
+
+// A source kept in memory, so downloads can be driven without the network.
+#[derive(Debug, Default)]
+pub struct FakeSource {
+    manga: Vec<Manga>,
+    // Chapters of each manga, by manga id.
+    chapters: HashMap<String, Vec<Chapter>>,
+    // Image urls of each chapter, by manga id and chapter encoding.
+    pages: HashMap<(String, String), Vec<String>>,
+    images: HashMap<String, Vec<u8>>,
+    // Every url an image was asked for, in order.
+    fetched: Mutex<Vec<String>>,
+}
+
+impl FakeSource {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_manga(mut self, manga: Manga) -> Self {
+        self.manga.push(manga);
+        self
+    }
+
+    // Add a chapter to `manga`, its pages being served at `urls`.
+    // A url given no image with `with_image` fails like a missing page would.
+    #[must_use]
+    pub fn with_chapter(mut self, manga: &Manga, chapter: Chapter, urls: &[&str]) -> Self {
+        let key = (manga.i.clone(), chapter.Chapter.clone());
+        self.pages
+            .insert(key, urls.iter().map(ToString::to_string).collect());
+        self.chapters
+            .entry(manga.i.clone())
+            .or_default()
+            .push(chapter);
+        self
+    }
+
+    #[must_use]
+    pub fn with_image(mut self, url: &str, bytes: Vec<u8>) -> Self {
+        self.images.insert(url.to_string(), bytes);
+        self
+    }
+
+    // Every url an image was asked for so far, in order.
+    #[must_use]
+    pub fn fetched(&self) -> Vec<String> {
+        self.fetched.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Source for FakeSource {
+    async fn catalogue(&self) -> Result<Vec<Manga>> {
+        Ok(self.manga.clone())
+    }
+
+    async fn chapters(&self, manga: &Manga) -> Result<Vec<Chapter>> {
+        Ok(self.chapters.get(&manga.i).cloned().unwrap_or_default())
+    }
+
+    async fn page_urls(&self, manga: &Manga, chapter: &Chapter) -> Result<Vec<String>> {
+        let key = (manga.i.clone(), chapter.Chapter.clone());
+        Ok(self.pages.get(&key).cloned().unwrap_or_default())
+    }
+
+    async fn fetch_image(&self, url: &str) -> Result<Vec<u8>> {
+        self.fetched.lock().unwrap().push(url.to_string());
+        self.images.get(url).cloned().ok_or_else(|| Error::Status {
+            url: url.to_string(),
+            status: reqwest::StatusCode::NOT_FOUND,
+            retry_after: None,
+        })
+    }
+}
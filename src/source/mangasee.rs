@@ -0,0 +1,195 @@
+use async_trait::async_trait;
+use brotlic::DecompressorReader;
+use regex::Regex;
+use std::{
+    io::{BufReader, Read},
+    sync::Arc,
+};
+
+use super::Source;
+use crate::{
+    chapter::{Chapter, ChapterInfo},
+    error::{Error, Result},
+    manga::Manga,
+    progress::{ProgressSink, Silent},
+    retry::{send, RetryPolicy},
+    scheduler::HostLimits,
+};
+
+const URL: &str = "https://mangasee123.com/";
+
+// Every request goes through the one client, which keeps connections to the hosts open for reuse.
+#[derive(Clone)]
+pub struct MangaseeSource {
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    hosts: Arc<HostLimits>,
+    // Where retries are reported.
+    progress: Arc<dyn ProgressSink>,
+}
+
+impl Default for MangaseeSource {
+    fn default() -> Self {
+        Self {
+            client: reqwest::Client::default(),
+            retry: RetryPolicy::default(),
+            hosts: Arc::default(),
+            progress: Arc::new(Silent),
+        }
+    }
+}
+
+impl MangaseeSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    #[must_use]
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    // Send at most `per_host` requests to any host at once.
+    #[must_use]
+    pub fn with_host_limit(mut self, per_host: usize) -> Self {
+        self.hosts = Arc::new(HostLimits::new(per_host));
+        self
+    }
+
+    // Identify as `user_agent` instead of the reqwest default.
+    pub fn with_user_agent(mut self, user_agent: &str) -> Result<Self> {
+        self.client = reqwest::Client::builder().user_agent(user_agent).build()?;
+        Ok(self)
+    }
+
+    // Body of a page, retried as a whole so a connection dropped mid body is retried too.
+    async fn get_text(&self, url: &str) -> Result<String> {
+        self.retry
+            .run(self.progress.as_ref(), || async {
+                let _permit = self.hosts.acquire(url).await;
+                Ok(send(self.client.get(url)).await?.text().await?)
+            })
+            .await
+    }
+
+    // Host the images of a chapter are served from.
+    async fn cur_path_name(&self, manga: &str, chapter: &Chapter) -> Result<String> {
+        let page = self
+            .get_text(&format!("{URL}read-online/{manga}-chapter-{chapter}.html"))
+            .await?;
+
+        Ok(
+            find_var(&page, r#"vm\.CurPathName = (.*);"#, "vm.CurPathName")?
+                .trim()
+                .replace('\"', ""),
+        )
+    }
+}
+
+#[async_trait]
+impl Source for MangaseeSource {
+    async fn catalogue(&self) -> Result<Vec<Manga>> {
+        let bin = self
+            .retry
+            .run(self.progress.as_ref(), || async {
+                let _permit = self.hosts.acquire(URL).await;
+                Ok(send(
+                    self.client
+                        .get(format!("{URL}search/").as_str())
+                        .header("Accept-Encoding", "br"),
+                )
+                .await?
+                .bytes()
+                .await?)
+            })
+            .await?;
+
+        // Convert the brotli compressed bytes to a string
+        let mut reader = BufReader::new(bin.as_ref());
+        let mut bytes = Vec::new();
+        let mut brotli = DecompressorReader::new(&mut reader);
+        brotli.read_to_end(&mut bytes)?;
+        let page = String::from_utf8_lossy(&bytes);
+
+        Ok(serde_json::from_str(find_var(
+            &page,
+            r#"vm\.Directory = (.*);"#,
+            "vm.Directory",
+        )?)?)
+    }
+
+    async fn chapters(&self, manga: &Manga) -> Result<Vec<Chapter>> {
+        // Any chapter page carries the full chapter list, the latest one is always valid.
+        let valid_chapter = Chapter::decode_id(&manga.l)?;
+
+        let page = self
+            .get_text(&format!(
+                "{URL}read-online/{}-chapter-{valid_chapter}.html",
+                manga.i
+            ))
+            .await?;
+        let chapters: Vec<Chapter> =
+            serde_json::from_str(find_var(&page, r#"vm\.CHAPTERS = (.*);"#, "vm.CHAPTERS")?)?;
+        for chapter in &chapters {
+            Chapter::decode_id(&chapter.Chapter)?;
+        }
+        Ok(chapters)
+    }
+
+    // Chapters with their release date, as listed on the manga's page.
+    async fn chapter_infos(&self, manga: &Manga) -> Result<Vec<ChapterInfo>> {
+        let page = self.get_text(&format!("{URL}manga/{}", manga.i)).await?;
+        let infos: Vec<ChapterInfo> =
+            serde_json::from_str(find_var(&page, r#"vm\.Chapters = (.*);"#, "vm.Chapters")?)?;
+        for info in &infos {
+            Chapter::decode_id(&info.Chapter)?;
+        }
+        Ok(infos)
+    }
+
+    async fn page_urls(&self, manga: &Manga, chapter: &Chapter) -> Result<Vec<String>> {
+        let host = self.cur_path_name(&manga.i, chapter).await?;
+        let pages = chapter
+            .Page
+            .parse::<usize>()
+            .map_err(|_| Error::Layout("the page count of the chapter"))?;
+        Ok((1..=pages)
+            .map(|page| {
+                format!(
+                    "https://{}/manga/{}{}/{:0>4}-{:0>3}.png",
+                    host,
+                    manga.i,
+                    chapter.directory(),
+                    chapter.to_url_id(),
+                    page
+                )
+            })
+            .collect())
+    }
+
+    async fn fetch_image(&self, url: &str) -> Result<Vec<u8>> {
+        self.retry
+            .run(self.progress.as_ref(), || async {
+                let _permit = self.hosts.acquire(url).await;
+                Ok(send(self.client.get(url)).await?.bytes().await?.to_vec())
+            })
+            .await
+    }
+}
+
+// Find the value of a javascript variable mangasee embeds into its pages.
+fn find_var<'a>(page: &'a str, regex: &str, name: &'static str) -> Result<&'a str> {
+    Regex::new(regex)
+        .expect("Failed to create regex")
+        .captures(page)
+        .and_then(|captures| captures.get(1))
+        .map(|capture| capture.as_str())
+        .ok_or(Error::Layout(name))
+}
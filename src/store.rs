@@ -0,0 +1,29 @@
+use std::{fs, io, path::Path};
+
+use crate::error::Result;
+
+// `file` read with `read`, `None` when it does not exist yet.
+// E.g. `read_if_exists(path, fs::read)` or `read_if_exists(path, fs::read_to_string)`.
+pub fn read_if_exists<'a, T>(
+    file: &'a Path,
+    read: impl FnOnce(&'a Path) -> io::Result<T>,
+) -> Result<Option<T>> {
+    match read(file) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Write `bytes` to `file`, creating the folders leading to it.
+// Written through a temporary file, so a crash never leaves it half written.
+pub fn write_atomic(file: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp = file.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, file)?;
+    Ok(())
+}
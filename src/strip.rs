@@ -0,0 +1,146 @@
+use clap::ValueEnum;
+use image::{imageops, DynamicImage, GrayImage, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// How far from the first pixel of a row the others may stray for the row to count as a gap, out of 255.
+const GAP_TOLERANCE: u8 = 8;
+
+// Slices are this many times as high as wide, when no height is set.
+const HEIGHT_RATIO: f32 = 1.5;
+
+// What is done with the pages of manhwa and manhua, which are cuts of one long strip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LongStrip {
+    // Saved as served.
+    Off,
+    // Stitched into a strip per chapter, then cut again at the gaps between panels.
+    Slice,
+    // Stitched into one tall image per chapter.
+    Single,
+}
+
+// How the strip of a chapter is cut once stitched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strip {
+    Whole,
+    // Slices about this high, `HEIGHT_RATIO` times the width when `None`.
+    Sliced(Option<u32>),
+}
+
+impl Strip {
+    // The pages of a chapter stitched and cut again.
+    #[must_use]
+    pub fn restrip(&self, images: Vec<DynamicImage>) -> Vec<DynamicImage> {
+        match stitch(images) {
+            Some(strip) => self.cut(strip),
+            None => Vec::new(),
+        }
+    }
+
+    // The stitched `strip` of a chapter cut into pages.
+    #[must_use]
+    pub fn cut(&self, strip: DynamicImage) -> Vec<DynamicImage> {
+        match self {
+            Strip::Whole => vec![strip],
+            Strip::Sliced(height) => {
+                let height = height.unwrap_or((strip.width() as f32 * HEIGHT_RATIO) as u32);
+                slice(&strip, height.max(1))
+            }
+        }
+    }
+}
+
+// `images` one below the other, scaled to the width most of them have.
+#[must_use]
+pub fn stitch(images: Vec<DynamicImage>) -> Option<DynamicImage> {
+    let mut widths = HashMap::<u32, usize>::new();
+    for image in &images {
+        *widths.entry(image.width()).or_default() += 1;
+    }
+    let (&width, _) = widths
+        .iter()
+        .max_by_key(|(&width, &count)| (count, width))?;
+
+    let images = images
+        .into_iter()
+        .map(|image| {
+            if image.width() == width {
+                image
+            } else {
+                let height =
+                    u64::from(image.height()) * u64::from(width) / u64::from(image.width());
+                image.resize_exact(width, height.max(1) as u32, imageops::FilterType::Lanczos3)
+            }
+        })
+        .collect::<Vec<_>>();
+    let height = images.iter().map(DynamicImage::height).sum();
+
+    // Grayscale pages stay grayscale, as the pipeline made them.
+    let gray = images
+        .iter()
+        .all(|image| matches!(image, DynamicImage::ImageLuma8(_)));
+    let mut y = 0;
+    if gray {
+        let mut strip = GrayImage::new(width, height);
+        for image in &images {
+            imageops::replace(&mut strip, &image.to_luma8(), 0, y);
+            y += i64::from(image.height());
+        }
+        Some(DynamicImage::ImageLuma8(strip))
+    } else {
+        let mut strip = RgbImage::new(width, height);
+        for image in &images {
+            imageops::replace(&mut strip, &image.to_rgb8(), 0, y);
+            y += i64::from(image.height());
+        }
+        Some(DynamicImage::ImageRgb8(strip))
+    }
+}
+
+// Cut `strip` into slices about `height` high, in the middle of the gaps between panels where there are any.
+#[must_use]
+pub fn slice(strip: &DynamicImage, height: u32) -> Vec<DynamicImage> {
+    let gray = strip.to_luma8();
+    let gaps = (0..gray.height())
+        .map(|y| {
+            let first = gray.get_pixel(0, y).0[0];
+            (0..gray.width()).all(|x| gray.get_pixel(x, y).0[0].abs_diff(first) <= GAP_TOLERANCE)
+        })
+        .collect::<Vec<_>>();
+
+    // A cut is looked for a quarter of `height` around it.
+    let reach = height / 4;
+    let mut slices = Vec::new();
+    let mut top = 0;
+    while gray.height() - top > height + reach {
+        let target = top + height;
+        let cut = gap_near(&gaps, target - reach, target + reach, target).unwrap_or(target);
+        slices.push(strip.crop_imm(0, top, strip.width(), cut - top));
+        top = cut;
+    }
+    slices.push(strip.crop_imm(0, top, strip.width(), strip.height() - top));
+    slices
+}
+
+// The middle of the run of gap rows between `from` and `to` closest to `target`.
+fn gap_near(gaps: &[bool], from: u32, to: u32, target: u32) -> Option<u32> {
+    let mut best: Option<u32> = None;
+    let mut y = from;
+    while y < to {
+        if !gaps[y as usize] {
+            y += 1;
+            continue;
+        }
+        let start = y;
+        while y < to && gaps[y as usize] {
+            y += 1;
+        }
+        let middle = (start + y) / 2;
+        if best.is_none_or(|best| middle.abs_diff(target) < best.abs_diff(target)) {
+            best = Some(middle);
+        }
+    }
+    best
+}
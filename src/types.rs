@@ -5,17 +5,35 @@ use std::{
     str::FromStr,
 };
 
+use clap::ValueEnum;
 use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum SaveType {
     Images,
     ImagesChapter,
     PdfSplit,
+    /// Every page of the series is held in memory until the pdf is written, use pdf-split for long series
     PdfSingle,
+    CbzSplit,
+    CbzSingle,
+    Epub,
     Urls,
 }
 
+impl SaveType {
+    // Whether the whole series goes into one file, which has to be written again to add chapters.
+    #[must_use]
+    pub fn is_whole_series(&self) -> bool {
+        matches!(
+            self,
+            SaveType::PdfSingle | SaveType::CbzSingle | SaveType::Epub
+        )
+    }
+}
+
 impl Debug for SaveType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -23,6 +41,9 @@ impl Debug for SaveType {
             SaveType::ImagesChapter => write!(f, "Images by Chapter"),
             SaveType::PdfSplit => write!(f, "Split PDFs"),
             SaveType::PdfSingle => write!(f, "Single PDF"),
+            SaveType::CbzSplit => write!(f, "Split CBZs"),
+            SaveType::CbzSingle => write!(f, "Single CBZ"),
+            SaveType::Epub => write!(f, "EPUB"),
             SaveType::Urls => write!(f, "URLs"),
         }
     }
@@ -31,36 +52,53 @@ impl Debug for SaveType {
 impl Display for SaveType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            SaveType::Urls => write!(f, "{} {}", "URLs             ".blue(), "Text file of URLS",),
+            SaveType::Urls => write!(f, "{} Text file of URLS", "URLs             ".blue()),
             SaveType::PdfSplit => write!(
                 f,
-                "{} {}\t{} {}",
+                "{} Split by chapters\t{} {}",
                 "PDFs Split       ".blue(),
-                "Split by chapters",
                 "Slow Save        ".dark_yellow(),
-                "High RAM Usage   ".red()
+                "Low RAM Usage    ".green()
             ),
             SaveType::PdfSingle => write!(
                 f,
-                "{} {}\t{} {}",
+                "{} Single pdf       \t{} {}",
                 "PDF Single       ".blue(),
-                "Single pdf       ",
                 "Slowest Save     ".dark_red(),
                 "High RAM Usage   ".red()
             ),
+            SaveType::CbzSplit => write!(
+                f,
+                "{} Split by chapters\t{} {}",
+                "CBZs Split       ".blue(),
+                "Fast Save        ".green(),
+                "Low RAM Usage    ".green()
+            ),
+            SaveType::CbzSingle => write!(
+                f,
+                "{} Single cbz       \t{} {}",
+                "CBZ Single       ".blue(),
+                "Fast Save        ".green(),
+                "Low RAM Usage    ".green()
+            ),
+            SaveType::Epub => write!(
+                f,
+                "{} Fixed layout     \t{} {}",
+                "EPUB             ".blue(),
+                "Fast Save        ".green(),
+                "Low RAM Usage    ".green()
+            ),
             SaveType::ImagesChapter => write!(
                 f,
-                "{} {}\t{} {}",
+                "{} Chapter Folders  \t{} {}",
                 "Images Split     ".blue(),
-                "Chapter Folders  ",
                 "Fast Save        ".green(),
                 "Low RAM Usage    ".green()
             ),
             SaveType::Images => write!(
                 f,
-                "{} {}\t{} {}",
+                "{} Single Folder    \t{} {}",
                 "Images           ".blue(),
-                "Single Folder    ",
                 "Fast Save        ".green(),
                 "Low RAM Usage    ".green()
             ),
@@ -68,6 +106,8 @@ impl Display for SaveType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum DownloadType {
     Single,
     Multi,
@@ -82,7 +122,8 @@ impl Display for DownloadType {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u8")]
 pub struct Thread {
     amount: u8,
 }
@@ -101,6 +142,20 @@ impl Thread {
     }
 }
 
+impl Default for Thread {
+    fn default() -> Self {
+        Self { amount: 1 }
+    }
+}
+
+impl TryFrom<u8> for Thread {
+    type Error = Box<dyn Error>;
+
+    fn try_from(amount: u8) -> Result<Self, Self::Error> {
+        Self::new(amount)
+    }
+}
+
 impl Display for Thread {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let Thread { amount } = self;